@@ -1,2 +1,5 @@
 #[cfg(target_arch = "x86_64")]
 pub mod x86_64;
+
+#[cfg(target_arch = "x86_64")]
+pub use x86_64::Register;
@@ -1,7 +1,12 @@
-use crate::{Context, Instruction, Operand};
+mod opcode;
+mod register;
+
+pub use register::Register;
+
+use crate::{DrContext, Instruction, MachineContext, Operand};
 use dynamorio_sys::*;
 
-impl Context {
+impl DrContext<'_> {
     pub fn create_mov_ld(&mut self, dst: Operand, src: Operand) -> Instruction {
         let raw = unsafe {
             instr_create_1dst_1src(self.context, OP_mov_ld as _, dst.raw, src.raw)
@@ -24,3 +29,172 @@ impl Context {
         }
     }
 }
+
+impl MachineContext {
+    /// Returns the value of the given general-purpose register, or `None` if it is not a
+    /// general-purpose register. Sub-registers such as `Register::Eax` and `Register::Ah` are
+    /// extracted from the pointer-sized register that contains them.
+    pub fn register(&self, register: Register) -> Option<usize> {
+        let full = register.to_pointer_sized()?;
+        let (shift, mask) = sub_register_bits(register);
+
+        Some((self.pointer_sized_register(full) >> shift) & mask)
+    }
+
+    /// Sets the value of the given general-purpose register. For sub-registers, only the bits
+    /// that belong to the sub-register are updated within the pointer-sized register that
+    /// contains them, e.g. setting `Register::Eax` leaves the upper half of `rax` as it is.
+    /// Returns `false` if it is not a general-purpose register.
+    pub fn set_register(&mut self, register: Register, value: usize) -> bool {
+        let full = match register.to_pointer_sized() {
+            Some(full) => full,
+            _ => return false,
+        };
+
+        let (shift, mask) = sub_register_bits(register);
+        let old = self.pointer_sized_register(full);
+
+        self.set_pointer_sized_register(full, (old & !(mask << shift)) | ((value & mask) << shift));
+
+        true
+    }
+
+    fn pointer_sized_register(&self, register: Register) -> usize {
+        unsafe {
+            reg_get_value(register.raw(), &self.mcontext as *const dr_mcontext_t as *mut dr_mcontext_t) as usize
+        }
+    }
+
+    fn set_pointer_sized_register(&mut self, register: Register, value: usize) {
+        unsafe {
+            reg_set_value(register.raw(), &mut self.mcontext, value as reg_t);
+        }
+    }
+
+    /// Returns the program counter, i.e. `rip`.
+    pub fn pc(&self) -> usize {
+        unsafe {
+            self.mcontext.__bindgen_anon_10.pc as usize
+        }
+    }
+
+    /// Sets the program counter, i.e. `rip`.
+    pub fn set_pc(&mut self, pc: usize) {
+        self.mcontext.__bindgen_anon_10.pc = pc as *mut u8;
+    }
+
+    /// Returns the flags register, i.e. `rflags`.
+    pub fn flags(&self) -> usize {
+        unsafe {
+            self.mcontext.__bindgen_anon_9.xflags as usize
+        }
+    }
+
+    /// Sets the flags register, i.e. `rflags`.
+    pub fn set_flags(&mut self, flags: usize) {
+        self.mcontext.__bindgen_anon_9.xflags = flags as reg_t;
+    }
+
+    /// Returns the stack pointer, i.e. `rsp`.
+    pub fn sp(&self) -> usize {
+        self.pointer_sized_register(Register::Rsp)
+    }
+
+    /// Sets the stack pointer, i.e. `rsp`.
+    pub fn set_sp(&mut self, sp: usize) {
+        self.set_pointer_sized_register(Register::Rsp, sp);
+    }
+
+    /// Returns the contents of the xmm register with the given index. The SIMD registers are only
+    /// valid if the machine context was retrieved with `DR_MC_MULTIMEDIA`.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn xmm(&self, index: usize) -> [u8; 16] {
+        self.simd_bytes(index)
+    }
+
+    /// Returns the contents of the ymm register with the given index.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn ymm(&self, index: usize) -> [u8; 32] {
+        self.simd_bytes(index)
+    }
+
+    /// Returns the contents of the zmm register with the given index.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn zmm(&self, index: usize) -> [u8; 64] {
+        self.simd_bytes(index)
+    }
+
+    /// Sets the lower 128 bits of the SIMD register with the given index, leaving the upper bits
+    /// as they are.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn set_xmm(&mut self, index: usize, value: [u8; 16]) {
+        self.set_simd_bytes(index, &value);
+    }
+
+    /// Sets the lower 256 bits of the SIMD register with the given index, leaving the upper bits
+    /// as they are.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn set_ymm(&mut self, index: usize, value: [u8; 32]) {
+        self.set_simd_bytes(index, &value);
+    }
+
+    /// Sets the zmm register with the given index.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn set_zmm(&mut self, index: usize, value: [u8; 64]) {
+        self.set_simd_bytes(index, &value);
+    }
+
+    /// Returns the AVX-512 opmask register with the given index, i.e. `k0` through `k7`. The
+    /// opmask registers are only valid if the machine context was retrieved with
+    /// `DR_MC_MULTIMEDIA`.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn opmask(&self, index: usize) -> dr_opmask_t {
+        self.mcontext.opmask[index]
+    }
+
+    /// Sets the AVX-512 opmask register with the given index.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn set_opmask(&mut self, index: usize, value: dr_opmask_t) {
+        self.mcontext.opmask[index] = value;
+    }
+
+    fn simd_bytes<const N: usize>(&self, index: usize) -> [u8; N] {
+        let mut bytes = [0u8; N];
+
+        unsafe {
+            bytes.copy_from_slice(&self.mcontext.simd[index].u8_[..N]);
+        }
+
+        bytes
+    }
+
+    fn set_simd_bytes(&mut self, index: usize, bytes: &[u8]) {
+        unsafe {
+            self.mcontext.simd[index].u8_[..bytes.len()].copy_from_slice(bytes);
+        }
+    }
+}
+
+/// Returns the shift and mask that select the bits of a general-purpose register within the
+/// pointer-sized register that contains it.
+fn sub_register_bits(register: Register) -> (u32, usize) {
+    let shift = match register {
+        Register::Ah | Register::Ch | Register::Dh | Register::Bh => 8,
+        _ => 0,
+    };
+
+    let mask = match register.size_in_bytes() {
+        8 => usize::MAX,
+        size => (1 << (size * 8)) - 1,
+    };
+
+    (shift, mask)
+}
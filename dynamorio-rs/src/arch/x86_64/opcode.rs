@@ -0,0 +1,84 @@
+use crate::Opcode;
+use dynamorio_sys::*;
+
+impl Opcode {
+    pub const MOV_LD: Self = Self(OP_mov_ld);
+    pub const MOV_ST: Self = Self(OP_mov_st);
+    pub const MOV_IMM: Self = Self(OP_mov_imm);
+    pub const MOV_SEG: Self = Self(OP_mov_seg);
+    pub const MOVZX: Self = Self(OP_movzx);
+    pub const MOVSX: Self = Self(OP_movsx);
+    pub const MOVSXD: Self = Self(OP_movsxd);
+    pub const LEA: Self = Self(OP_lea);
+    pub const PUSH: Self = Self(OP_push);
+    pub const POP: Self = Self(OP_pop);
+    pub const PUSHF: Self = Self(OP_pushf);
+    pub const POPF: Self = Self(OP_popf);
+    pub const XCHG: Self = Self(OP_xchg);
+    pub const XADD: Self = Self(OP_xadd);
+    pub const CMPXCHG: Self = Self(OP_cmpxchg);
+    pub const ADD: Self = Self(OP_add);
+    pub const SUB: Self = Self(OP_sub);
+    pub const INC: Self = Self(OP_inc);
+    pub const DEC: Self = Self(OP_dec);
+    pub const NEG: Self = Self(OP_neg);
+    pub const IMUL: Self = Self(OP_imul);
+    pub const MUL: Self = Self(OP_mul);
+    pub const IDIV: Self = Self(OP_idiv);
+    pub const DIV: Self = Self(OP_div);
+    pub const AND: Self = Self(OP_and);
+    pub const OR: Self = Self(OP_or);
+    pub const XOR: Self = Self(OP_xor);
+    pub const NOT: Self = Self(OP_not);
+    pub const SHL: Self = Self(OP_shl);
+    pub const SHR: Self = Self(OP_shr);
+    pub const SAR: Self = Self(OP_sar);
+    pub const CMP: Self = Self(OP_cmp);
+    pub const TEST: Self = Self(OP_test);
+    pub const JMP: Self = Self(OP_jmp);
+    pub const JMP_SHORT: Self = Self(OP_jmp_short);
+    pub const JMP_IND: Self = Self(OP_jmp_ind);
+    pub const JMP_FAR: Self = Self(OP_jmp_far);
+    pub const JMP_FAR_IND: Self = Self(OP_jmp_far_ind);
+    pub const JO: Self = Self(OP_jo);
+    pub const JNO: Self = Self(OP_jno);
+    pub const JB: Self = Self(OP_jb);
+    pub const JNB: Self = Self(OP_jnb);
+    pub const JZ: Self = Self(OP_jz);
+    pub const JNZ: Self = Self(OP_jnz);
+    pub const JBE: Self = Self(OP_jbe);
+    pub const JNBE: Self = Self(OP_jnbe);
+    pub const JS: Self = Self(OP_js);
+    pub const JNS: Self = Self(OP_jns);
+    pub const JP: Self = Self(OP_jp);
+    pub const JNP: Self = Self(OP_jnp);
+    pub const JL: Self = Self(OP_jl);
+    pub const JNL: Self = Self(OP_jnl);
+    pub const JLE: Self = Self(OP_jle);
+    pub const JNLE: Self = Self(OP_jnle);
+    pub const JECXZ: Self = Self(OP_jecxz);
+    pub const LOOP: Self = Self(OP_loop);
+    pub const CALL: Self = Self(OP_call);
+    pub const CALL_IND: Self = Self(OP_call_ind);
+    pub const CALL_FAR: Self = Self(OP_call_far);
+    pub const CALL_FAR_IND: Self = Self(OP_call_far_ind);
+    pub const RET: Self = Self(OP_ret);
+    pub const RET_FAR: Self = Self(OP_ret_far);
+    pub const IRET: Self = Self(OP_iret);
+    pub const ENTER: Self = Self(OP_enter);
+    pub const LEAVE: Self = Self(OP_leave);
+    pub const INT: Self = Self(OP_int);
+    pub const INT3: Self = Self(OP_int3);
+    pub const SYSCALL: Self = Self(OP_syscall);
+    pub const SYSENTER: Self = Self(OP_sysenter);
+    pub const CPUID: Self = Self(OP_cpuid);
+    pub const RDTSC: Self = Self(OP_rdtsc);
+    pub const RDTSCP: Self = Self(OP_rdtscp);
+    pub const NOP: Self = Self(OP_nop);
+    pub const HLT: Self = Self(OP_hlt);
+    pub const UD2: Self = Self(OP_ud2);
+    pub const MOVS: Self = Self(OP_movs);
+    pub const REP_MOVS: Self = Self(OP_rep_movs);
+    pub const STOS: Self = Self(OP_stos);
+    pub const REP_STOS: Self = Self(OP_rep_stos);
+}
@@ -0,0 +1,331 @@
+use core::ffi::CStr;
+use core::fmt;
+use dynamorio_sys::*;
+
+macro_rules! registers {
+    ($($name:ident => $raw:ident,)*) => {
+        /// A register on x86-64, as identified by DynamoRIO.
+        #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+        pub enum Register {
+            $($name,)*
+        }
+
+        impl Register {
+            /// Returns the register for the given raw register ID, or `None` if it is `DR_REG_NULL`
+            /// or not a register that is covered by this type.
+            pub fn from_raw(raw: reg_id_t) -> Option<Self> {
+                match raw as u32 {
+                    $($raw => Some(Self::$name),)*
+                    _ => None,
+                }
+            }
+
+            pub fn raw(&self) -> reg_id_t {
+                match self {
+                    $(Self::$name => $raw as reg_id_t,)*
+                }
+            }
+        }
+    };
+}
+
+registers! {
+    // 64-bit general-purpose registers.
+    Rax => DR_REG_RAX,
+    Rcx => DR_REG_RCX,
+    Rdx => DR_REG_RDX,
+    Rbx => DR_REG_RBX,
+    Rsp => DR_REG_RSP,
+    Rbp => DR_REG_RBP,
+    Rsi => DR_REG_RSI,
+    Rdi => DR_REG_RDI,
+    R8 => DR_REG_R8,
+    R9 => DR_REG_R9,
+    R10 => DR_REG_R10,
+    R11 => DR_REG_R11,
+    R12 => DR_REG_R12,
+    R13 => DR_REG_R13,
+    R14 => DR_REG_R14,
+    R15 => DR_REG_R15,
+
+    // 32-bit general-purpose registers.
+    Eax => DR_REG_EAX,
+    Ecx => DR_REG_ECX,
+    Edx => DR_REG_EDX,
+    Ebx => DR_REG_EBX,
+    Esp => DR_REG_ESP,
+    Ebp => DR_REG_EBP,
+    Esi => DR_REG_ESI,
+    Edi => DR_REG_EDI,
+    R8d => DR_REG_R8D,
+    R9d => DR_REG_R9D,
+    R10d => DR_REG_R10D,
+    R11d => DR_REG_R11D,
+    R12d => DR_REG_R12D,
+    R13d => DR_REG_R13D,
+    R14d => DR_REG_R14D,
+    R15d => DR_REG_R15D,
+
+    // 16-bit general-purpose registers.
+    Ax => DR_REG_AX,
+    Cx => DR_REG_CX,
+    Dx => DR_REG_DX,
+    Bx => DR_REG_BX,
+    Sp => DR_REG_SP,
+    Bp => DR_REG_BP,
+    Si => DR_REG_SI,
+    Di => DR_REG_DI,
+    R8w => DR_REG_R8W,
+    R9w => DR_REG_R9W,
+    R10w => DR_REG_R10W,
+    R11w => DR_REG_R11W,
+    R12w => DR_REG_R12W,
+    R13w => DR_REG_R13W,
+    R14w => DR_REG_R14W,
+    R15w => DR_REG_R15W,
+
+    // 8-bit general-purpose registers.
+    Al => DR_REG_AL,
+    Cl => DR_REG_CL,
+    Dl => DR_REG_DL,
+    Bl => DR_REG_BL,
+    Ah => DR_REG_AH,
+    Ch => DR_REG_CH,
+    Dh => DR_REG_DH,
+    Bh => DR_REG_BH,
+    R8l => DR_REG_R8L,
+    R9l => DR_REG_R9L,
+    R10l => DR_REG_R10L,
+    R11l => DR_REG_R11L,
+    R12l => DR_REG_R12L,
+    R13l => DR_REG_R13L,
+    R14l => DR_REG_R14L,
+    R15l => DR_REG_R15L,
+    Spl => DR_REG_SPL,
+    Bpl => DR_REG_BPL,
+    Sil => DR_REG_SIL,
+    Dil => DR_REG_DIL,
+
+    // Segment registers.
+    Es => DR_REG_ES,
+    Cs => DR_REG_CS,
+    Ss => DR_REG_SS,
+    Ds => DR_REG_DS,
+    Fs => DR_REG_FS,
+    Gs => DR_REG_GS,
+
+    // 128-bit SSE registers.
+    Xmm0 => DR_REG_XMM0,
+    Xmm1 => DR_REG_XMM1,
+    Xmm2 => DR_REG_XMM2,
+    Xmm3 => DR_REG_XMM3,
+    Xmm4 => DR_REG_XMM4,
+    Xmm5 => DR_REG_XMM5,
+    Xmm6 => DR_REG_XMM6,
+    Xmm7 => DR_REG_XMM7,
+    Xmm8 => DR_REG_XMM8,
+    Xmm9 => DR_REG_XMM9,
+    Xmm10 => DR_REG_XMM10,
+    Xmm11 => DR_REG_XMM11,
+    Xmm12 => DR_REG_XMM12,
+    Xmm13 => DR_REG_XMM13,
+    Xmm14 => DR_REG_XMM14,
+    Xmm15 => DR_REG_XMM15,
+    Xmm16 => DR_REG_XMM16,
+    Xmm17 => DR_REG_XMM17,
+    Xmm18 => DR_REG_XMM18,
+    Xmm19 => DR_REG_XMM19,
+    Xmm20 => DR_REG_XMM20,
+    Xmm21 => DR_REG_XMM21,
+    Xmm22 => DR_REG_XMM22,
+    Xmm23 => DR_REG_XMM23,
+    Xmm24 => DR_REG_XMM24,
+    Xmm25 => DR_REG_XMM25,
+    Xmm26 => DR_REG_XMM26,
+    Xmm27 => DR_REG_XMM27,
+    Xmm28 => DR_REG_XMM28,
+    Xmm29 => DR_REG_XMM29,
+    Xmm30 => DR_REG_XMM30,
+    Xmm31 => DR_REG_XMM31,
+
+    // 256-bit AVX registers.
+    Ymm0 => DR_REG_YMM0,
+    Ymm1 => DR_REG_YMM1,
+    Ymm2 => DR_REG_YMM2,
+    Ymm3 => DR_REG_YMM3,
+    Ymm4 => DR_REG_YMM4,
+    Ymm5 => DR_REG_YMM5,
+    Ymm6 => DR_REG_YMM6,
+    Ymm7 => DR_REG_YMM7,
+    Ymm8 => DR_REG_YMM8,
+    Ymm9 => DR_REG_YMM9,
+    Ymm10 => DR_REG_YMM10,
+    Ymm11 => DR_REG_YMM11,
+    Ymm12 => DR_REG_YMM12,
+    Ymm13 => DR_REG_YMM13,
+    Ymm14 => DR_REG_YMM14,
+    Ymm15 => DR_REG_YMM15,
+    Ymm16 => DR_REG_YMM16,
+    Ymm17 => DR_REG_YMM17,
+    Ymm18 => DR_REG_YMM18,
+    Ymm19 => DR_REG_YMM19,
+    Ymm20 => DR_REG_YMM20,
+    Ymm21 => DR_REG_YMM21,
+    Ymm22 => DR_REG_YMM22,
+    Ymm23 => DR_REG_YMM23,
+    Ymm24 => DR_REG_YMM24,
+    Ymm25 => DR_REG_YMM25,
+    Ymm26 => DR_REG_YMM26,
+    Ymm27 => DR_REG_YMM27,
+    Ymm28 => DR_REG_YMM28,
+    Ymm29 => DR_REG_YMM29,
+    Ymm30 => DR_REG_YMM30,
+    Ymm31 => DR_REG_YMM31,
+
+    // 512-bit AVX-512 registers.
+    Zmm0 => DR_REG_ZMM0,
+    Zmm1 => DR_REG_ZMM1,
+    Zmm2 => DR_REG_ZMM2,
+    Zmm3 => DR_REG_ZMM3,
+    Zmm4 => DR_REG_ZMM4,
+    Zmm5 => DR_REG_ZMM5,
+    Zmm6 => DR_REG_ZMM6,
+    Zmm7 => DR_REG_ZMM7,
+    Zmm8 => DR_REG_ZMM8,
+    Zmm9 => DR_REG_ZMM9,
+    Zmm10 => DR_REG_ZMM10,
+    Zmm11 => DR_REG_ZMM11,
+    Zmm12 => DR_REG_ZMM12,
+    Zmm13 => DR_REG_ZMM13,
+    Zmm14 => DR_REG_ZMM14,
+    Zmm15 => DR_REG_ZMM15,
+    Zmm16 => DR_REG_ZMM16,
+    Zmm17 => DR_REG_ZMM17,
+    Zmm18 => DR_REG_ZMM18,
+    Zmm19 => DR_REG_ZMM19,
+    Zmm20 => DR_REG_ZMM20,
+    Zmm21 => DR_REG_ZMM21,
+    Zmm22 => DR_REG_ZMM22,
+    Zmm23 => DR_REG_ZMM23,
+    Zmm24 => DR_REG_ZMM24,
+    Zmm25 => DR_REG_ZMM25,
+    Zmm26 => DR_REG_ZMM26,
+    Zmm27 => DR_REG_ZMM27,
+    Zmm28 => DR_REG_ZMM28,
+    Zmm29 => DR_REG_ZMM29,
+    Zmm30 => DR_REG_ZMM30,
+    Zmm31 => DR_REG_ZMM31,
+
+    // AVX-512 opmask registers.
+    K0 => DR_REG_K0,
+    K1 => DR_REG_K1,
+    K2 => DR_REG_K2,
+    K3 => DR_REG_K3,
+    K4 => DR_REG_K4,
+    K5 => DR_REG_K5,
+    K6 => DR_REG_K6,
+    K7 => DR_REG_K7,
+}
+
+impl Register {
+    /// Returns the name of the register as used by DynamoRIO's disassembler, e.g. `"rax"`.
+    pub fn name(&self) -> &'static str {
+        unsafe {
+            CStr::from_ptr(get_register_name(self.raw()))
+                .to_str()
+                .unwrap_or("")
+        }
+    }
+
+    /// Returns the size of the register as an operand size, e.g. `OPSZ_8` for `Register::Rax`.
+    pub fn size(&self) -> opnd_size_t {
+        unsafe {
+            reg_get_size(self.raw())
+        }
+    }
+
+    /// Returns the size of the register in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        unsafe {
+            opnd_size_in_bytes(self.size()) as usize
+        }
+    }
+
+    pub fn is_gpr(&self) -> bool {
+        unsafe {
+            reg_is_gpr(self.raw()) != 0
+        }
+    }
+
+    pub fn is_pointer_sized(&self) -> bool {
+        unsafe {
+            reg_is_pointer_sized(self.raw()) != 0
+        }
+    }
+
+    pub fn is_segment(&self) -> bool {
+        unsafe {
+            reg_is_segment(self.raw()) != 0
+        }
+    }
+
+    pub fn is_simd(&self) -> bool {
+        unsafe {
+            reg_is_simd(self.raw()) != 0
+        }
+    }
+
+    pub fn is_opmask(&self) -> bool {
+        unsafe {
+            reg_is_opmask(self.raw()) != 0
+        }
+    }
+
+    /// Returns whether the register overlaps with the other register, e.g. `Register::Eax` and
+    /// `Register::Ah` both overlap with `Register::Rax`.
+    pub fn overlaps(&self, other: Register) -> bool {
+        unsafe {
+            reg_overlap(self.raw(), other.raw()) != 0
+        }
+    }
+
+    /// Returns the pointer-sized register that contains this general-purpose register, e.g.
+    /// `Register::Rax` for `Register::Al`, or `None` if it is not a general-purpose register.
+    pub fn to_pointer_sized(self) -> Option<Self> {
+        if !self.is_gpr() {
+            return None;
+        }
+
+        Self::from_raw(unsafe { reg_to_pointer_sized(self.raw()) })
+    }
+
+    /// Returns the 32-bit variant of this general-purpose register, or `None` if it is not a
+    /// general-purpose register.
+    pub fn to_32bit(self) -> Option<Self> {
+        let register = self.to_pointer_sized()?;
+
+        Self::from_raw(unsafe { reg_64_to_32(register.raw()) })
+    }
+
+    /// Returns the 16-bit variant of this general-purpose register, or `None` if it is not a
+    /// general-purpose register.
+    pub fn to_16bit(self) -> Option<Self> {
+        let register = self.to_32bit()?;
+
+        Self::from_raw(unsafe { reg_32_to_16(register.raw()) })
+    }
+
+    /// Returns the lower 8-bit variant of this general-purpose register, or `None` if it is not a
+    /// general-purpose register.
+    pub fn to_8bit(self) -> Option<Self> {
+        let register = self.to_32bit()?;
+
+        Self::from_raw(unsafe { reg_32_to_8(register.raw()) })
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
@@ -1,14 +1,26 @@
-use crate::{Instruction, MachineContext, Operand};
+use crate::{Instruction, MachineContext, Opcode, Operand};
+use core::convert::Infallible;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
 use dynamorio_sys::*;
 
-pub struct Context {
+/// The DynamoRIO context of a thread. A drcontext is only valid on its own thread and only for the
+/// duration of the event it was passed to, which is what the lifetime `'cb` represents. As such,
+/// `DrContext` is neither `Send` nor `Sync`, and event handlers only ever receive it by
+/// reference.
+pub struct DrContext<'cb> {
     pub(crate) context: *mut core::ffi::c_void,
+    _marker: PhantomData<&'cb ()>,
 }
 
-impl Context {
-    pub fn from_raw(context: *mut core::ffi::c_void) -> Self {
+impl<'cb> DrContext<'cb> {
+    /// # Safety
+    /// The caller must ensure that `context` is the drcontext of the current thread and that it
+    /// remains valid for `'cb`.
+    pub unsafe fn from_raw(context: *mut core::ffi::c_void) -> Self {
         Self {
             context,
+            _marker: PhantomData,
         }
     }
 
@@ -16,13 +28,15 @@ impl Context {
         self.context
     }
 
-    pub fn current() -> Self {
+    /// Returns a guard for the drcontext of the current thread. Like the drcontext itself, the
+    /// guard cannot be sent to another thread.
+    pub fn current() -> CurrentContext {
         let context = unsafe {
             dr_get_current_drcontext()
         };
 
-        Self {
-            context,
+        CurrentContext {
+            context: unsafe { DrContext::from_raw(context) },
         }
     }
 
@@ -75,13 +89,44 @@ impl Context {
         }
 
         MachineContext {
-            _mcontext: mcontext,
+            mcontext,
+        }
+    }
+
+    /// Sets the machine context of the thread. Only the parts selected by the flags of the machine
+    /// context are written. Returns whether the machine context was set successfully.
+    pub fn set_machine_context(&mut self, mcontext: &MachineContext) -> bool {
+        unsafe {
+            dr_set_mcontext(self.context, &mcontext.mcontext) != 0
+        }
+    }
+
+    /// Resumes execution of the application with the given machine context, typically to skip a
+    /// function or to recover from a fault. As the full machine context is restored, it must have
+    /// been retrieved with `DR_MC_ALL`. This function only returns if the redirect failed.
+    ///
+    /// This may only be called from a clean call. The signal and restore state events should
+    /// modify the machine context they are passed instead, where a signal handler has DynamoRIO
+    /// redirect execution by returning the redirect action from the signal event.
+    pub fn redirect(&mut self, mut mcontext: MachineContext) -> Result<Infallible, RedirectError> {
+        let all = dr_mcontext_flags_t::DR_MC_ALL;
+
+        if mcontext.mcontext.flags.0 & all.0 != all.0 {
+            return Err(RedirectError::IncompleteMachineContext);
+        }
+
+        mcontext.mcontext.size = core::mem::size_of::<dr_mcontext_t>();
+
+        unsafe {
+            dr_redirect_execution(&mut mcontext.mcontext);
         }
+
+        Err(RedirectError::Failed)
     }
 
     pub fn create_instruction(
         &self,
-        opcode: u32,
+        opcode: Opcode,
         targets: &[Operand],
         sources: &[Operand],
     ) -> Option<Instruction> {
@@ -89,7 +134,7 @@ impl Context {
             (1, 1) => unsafe {
                 instr_create_1dst_1src(
                     self.context,
-                    opcode as i32,
+                    opcode.raw() as i32,
                     targets[0].raw,
                     sources[0].raw,
                 )
@@ -97,7 +142,7 @@ impl Context {
             (1, 2) => unsafe {
                 instr_create_1dst_2src(
                     self.context,
-                    opcode as i32,
+                    opcode.raw() as i32,
                     targets[0].raw,
                     sources[0].raw,
                     sources[1].raw,
@@ -117,22 +162,54 @@ impl Context {
     }
 }
 
-pub struct BeforeSyscallContext {
-    context: Context,
+/// The reason why [`DrContext::redirect`] failed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RedirectError {
+    /// The machine context was not retrieved with `DR_MC_ALL`, such that redirecting to it would
+    /// clobber the registers that are missing from it.
+    IncompleteMachineContext,
+    /// DynamoRIO failed to redirect execution.
+    Failed,
 }
 
-impl BeforeSyscallContext {
-    pub fn from_raw(context: *mut core::ffi::c_void) -> Self {
+/// Guard for the drcontext of the current thread, as returned by [`DrContext::current`].
+pub struct CurrentContext {
+    context: DrContext<'static>,
+}
+
+impl Deref for CurrentContext {
+    type Target = DrContext<'static>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.context
+    }
+}
+
+impl DerefMut for CurrentContext {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.context
+    }
+}
+
+pub struct BeforeSyscallContext<'cb> {
+    context: DrContext<'cb>,
+}
+
+impl<'cb> BeforeSyscallContext<'cb> {
+    /// # Safety
+    /// The caller must ensure that `context` is the drcontext passed to the pre-syscall event and
+    /// that it remains valid for `'cb`.
+    pub unsafe fn from_raw(context: *mut core::ffi::c_void) -> Self {
         Self {
-            context: Context::from_raw(context),
+            context: DrContext::from_raw(context),
         }
     }
 
-    pub fn context(&self) -> &Context {
+    pub fn context(&self) -> &DrContext<'cb> {
         &self.context
     }
 
-    pub fn context_mut(&mut self) -> &mut Context {
+    pub fn context_mut(&mut self) -> &mut DrContext<'cb> {
         &mut self.context
     }
 
@@ -169,22 +246,25 @@ impl BeforeSyscallContext {
     }
 }
 
-pub struct AfterSyscallContext {
-    context: Context,
+pub struct AfterSyscallContext<'cb> {
+    context: DrContext<'cb>,
 }
 
-impl AfterSyscallContext {
-    pub fn from_raw(context: *mut core::ffi::c_void) -> Self {
+impl<'cb> AfterSyscallContext<'cb> {
+    /// # Safety
+    /// The caller must ensure that `context` is the drcontext passed to the post-syscall event and
+    /// that it remains valid for `'cb`.
+    pub unsafe fn from_raw(context: *mut core::ffi::c_void) -> Self {
         Self {
-            context: Context::from_raw(context),
+            context: DrContext::from_raw(context),
         }
     }
 
-    pub fn context(&self) -> &Context {
+    pub fn context(&self) -> &DrContext<'cb> {
         &self.context
     }
 
-    pub fn context_mut(&mut self) -> &mut Context {
+    pub fn context_mut(&mut self) -> &mut DrContext<'cb> {
         &mut self.context
     }
 
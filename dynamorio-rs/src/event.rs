@@ -1,32 +1,43 @@
-use atomic::{Atomic, Ordering};
-use crate::{ClientId, Context};
+use crate::{ClientId, DrContext};
 use crate::closure::Closure;
 use drstd::sync::{Arc, Mutex};
 use dynamorio_sys::*;
 
-type ForkHandler = fn(&mut Context) -> ();
-type NudgeHandler = fn(&mut Context, u64) -> ();
-
-static FORK_HANDLER: Atomic<Option<ForkHandler>> = Atomic::new(None);
-static NUDGE_HANDLER: Atomic<Option<NudgeHandler>> = Atomic::new(None);
-
 pub trait ExitHandler {
     fn exit(&mut self);
 }
 
-extern "C" fn fork_event(context: *mut core::ffi::c_void) {
-    let mut context = Context::from_raw(context);
+pub trait ForkHandler {
+    /// Invoked in the child process after the application forked.
+    fn fork(&mut self, context: &mut DrContext<'_>);
+}
+
+pub trait NudgeHandler {
+    /// Invoked when the client is nudged with the given argument.
+    fn nudge(&mut self, context: &mut DrContext<'_>, argument: u64);
+}
+
+#[cfg(unix)]
+extern "C" fn fork_event<T: ForkHandler>(
+    context: *mut core::ffi::c_void,
+    handler: &Mutex<T>,
+) {
+    let mut context = unsafe { DrContext::from_raw(context) };
 
-    if let Some(handler) = FORK_HANDLER.load(Ordering::Relaxed) {
-        handler(&mut context)
+    if let Ok(mut handler) = handler.lock() {
+        handler.fork(&mut context)
     }
 }
 
-extern "C" fn nudge_event(context: *mut core::ffi::c_void, argument: u64) {
-    let mut context = Context::from_raw(context);
+extern "C" fn nudge_event<T: NudgeHandler>(
+    context: *mut core::ffi::c_void,
+    argument: u64,
+    handler: &Mutex<T>,
+) {
+    let mut context = unsafe { DrContext::from_raw(context) };
 
-    if let Some(handler) = NUDGE_HANDLER.load(Ordering::Relaxed) {
-        handler(&mut context, argument)
+    if let Ok(mut handler) = handler.lock() {
+        handler.nudge(&mut context, argument)
     }
 }
 
@@ -83,26 +94,134 @@ pub fn register_exit_handler<T: ExitHandler>(handler: &Arc<Mutex<T>>) -> Registe
     }
 }
 
-#[cfg(unix)]
-pub fn register_fork_event(func: fn(&mut Context) -> ()) {
-    FORK_HANDLER.store(Some(func), Ordering::Relaxed);
+pub struct RegisteredForkHandler<T: ForkHandler> {
+    _handler: Arc<Mutex<T>>,
+    #[cfg(unix)]
+    closure: Closure,
+}
 
-    unsafe {
-        dr_register_fork_init_event(Some(fork_event));
+unsafe impl<T: ForkHandler> Send for RegisteredForkHandler<T> {}
+unsafe impl<T: ForkHandler> Sync for RegisteredForkHandler<T> {}
+
+impl<T: ForkHandler> Drop for RegisteredForkHandler<T> {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        {
+            let func: extern "C" fn(*mut core::ffi::c_void) = unsafe {
+                core::mem::transmute(self.closure.code())
+            };
+
+            unsafe {
+                dr_unregister_fork_init_event(Some(func));
+            }
+        }
     }
 }
 
-#[cfg(not(unix))]
-pub fn register_fork_event(func: fn(&mut Context) -> ()) {
+/// Registers a handler for the fork event. DynamoRIO invokes the handler in the child process
+/// after the application forked. On platforms without fork, the handler is never invoked.
+pub fn register_fork_handler<T: ForkHandler>(handler: &Arc<Mutex<T>>) -> RegisteredForkHandler<T> {
+    #[cfg(unix)]
+    let closure = Closure::new(
+        1,
+        unsafe {
+            core::mem::transmute(fork_event::<T> as extern "C" fn(_, _))
+        },
+        Arc::as_ptr(handler) as *mut core::ffi::c_void,
+    );
+
+    #[cfg(unix)]
+    {
+        let func: extern "C" fn(*mut core::ffi::c_void) = unsafe {
+            core::mem::transmute(closure.code())
+        };
+
+        unsafe {
+            dr_register_fork_init_event(Some(func));
+        }
+    }
+
+    RegisteredForkHandler {
+        _handler: Arc::clone(handler),
+        #[cfg(unix)]
+        closure,
+    }
 }
 
-pub fn register_nudge_event(
-    func: fn(&mut Context, u64) -> (),
+pub struct RegisteredNudgeHandler<T: NudgeHandler> {
+    _handler: Arc<Mutex<T>>,
     client_id: ClientId,
-) {
-    NUDGE_HANDLER.store(Some(func), Ordering::Relaxed);
+    closure: Closure,
+}
+
+unsafe impl<T: NudgeHandler> Send for RegisteredNudgeHandler<T> {}
+unsafe impl<T: NudgeHandler> Sync for RegisteredNudgeHandler<T> {}
+
+impl<T: NudgeHandler> Drop for RegisteredNudgeHandler<T> {
+    fn drop(&mut self) {
+        let func: extern "C" fn(*mut core::ffi::c_void, u64) = unsafe {
+            core::mem::transmute(self.closure.code())
+        };
+
+        unsafe {
+            dr_unregister_nudge_event(Some(func), self.client_id.0);
+        }
+    }
+}
+
+/// Registers a handler for the nudge event of the client with the given ID. DynamoRIO invokes the
+/// handler when the client is nudged, e.g. through `drnudgeunix` or `drconfig -nudge`.
+pub fn register_nudge_handler<T: NudgeHandler>(
+    handler: &Arc<Mutex<T>>,
+    client_id: ClientId,
+) -> RegisteredNudgeHandler<T> {
+    let closure = Closure::new(
+        2,
+        unsafe {
+            core::mem::transmute(nudge_event::<T> as extern "C" fn(_, _, _))
+        },
+        Arc::as_ptr(handler) as *mut core::ffi::c_void,
+    );
 
+    let func: extern "C" fn(*mut core::ffi::c_void, u64) = unsafe {
+        core::mem::transmute(closure.code())
+    };
+
+    unsafe {
+        dr_register_nudge_event(Some(func), client_id.0);
+    }
+
+    RegisteredNudgeHandler {
+        _handler: Arc::clone(handler),
+        client_id,
+        closure,
+    }
+}
+
+/// Nudges the client with the given ID in the current process, which invokes its nudge handler
+/// with the given argument. Returns whether the nudge was delivered.
+pub fn nudge_client(client_id: ClientId, argument: u64) -> bool {
     unsafe {
-        dr_register_nudge_event(Some(nudge_event), client_id.0);
+        dr_nudge_client(client_id.0, argument) != 0
     }
 }
+
+/// Nudges the client with the given ID in the process with the given ID, which may be another
+/// process running under DynamoRIO. On Windows, this waits for up to `timeout_ms` milliseconds
+/// for the nudge to complete, where zero does not wait at all and `u32::MAX` waits indefinitely.
+pub fn nudge_client_ex(
+    process_id: process_id_t,
+    client_id: ClientId,
+    argument: u64,
+    timeout_ms: u32,
+) -> Result<(), dr_config_status_t> {
+    let status = unsafe {
+        dr_nudge_client_ex(process_id, client_id.0, argument, timeout_ms)
+    };
+
+    if status != dr_config_status_t::DR_SUCCESS {
+        return Err(status);
+    }
+
+    Ok(())
+}
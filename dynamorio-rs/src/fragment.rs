@@ -0,0 +1,150 @@
+use crate::{DrContext, InstrListRef};
+use crate::closure::Closure;
+use drstd::sync::{Arc, Mutex};
+use dynamorio_sys::*;
+
+/// The tag that uniquely identifies a fragment, i.e. a basic block or trace, in the code cache.
+/// The tag remains the same for as long as the fragment exists, which makes it suitable as a key
+/// for per-block data.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct BlockTag(usize);
+
+impl BlockTag {
+    pub fn from_raw(tag: *mut core::ffi::c_void) -> Self {
+        Self(tag as usize)
+    }
+
+    pub fn raw(&self) -> *mut core::ffi::c_void {
+        self.0 as *mut core::ffi::c_void
+    }
+
+    /// Returns the application address at which the fragment starts.
+    pub fn app_pc(&self) -> usize {
+        unsafe {
+            dr_fragment_app_pc(self.raw()) as usize
+        }
+    }
+}
+
+/// Describes the code fragment in which a fault occurred, as passed to the signal and restore
+/// state events.
+pub struct FaultFragmentInfo<'a> {
+    context: *mut core::ffi::c_void,
+    raw: &'a dr_fault_fragment_info_t,
+}
+
+impl<'a> FaultFragmentInfo<'a> {
+    /// # Safety
+    /// The caller must ensure that `context` is the drcontext of the current thread and that the
+    /// instruction list referenced by `raw`, if any, outlives `'a`.
+    pub unsafe fn from_raw(context: *mut core::ffi::c_void, raw: &'a dr_fault_fragment_info_t) -> Self {
+        Self {
+            context,
+            raw,
+        }
+    }
+
+    pub fn raw(&self) -> &dr_fault_fragment_info_t {
+        self.raw
+    }
+
+    /// Returns the tag of the fragment, or `None` if the fault did not occur in the code cache.
+    pub fn tag(&self) -> Option<BlockTag> {
+        if self.raw.tag.is_null() {
+            return None;
+        }
+
+        Some(BlockTag::from_raw(self.raw.tag))
+    }
+
+    /// Returns the start of the fragment in the code cache.
+    pub fn cache_start_pc(&self) -> usize {
+        self.raw.cache_start_pc as usize
+    }
+
+    /// Returns whether the fragment is a trace rather than a basic block.
+    pub fn is_trace(&self) -> bool {
+        self.raw.is_trace != 0
+    }
+
+    /// Returns whether the application code of the fragment is unchanged since the fragment was
+    /// built.
+    pub fn app_code_consistent(&self) -> bool {
+        self.raw.app_code_consistent != 0
+    }
+
+    /// Returns the instruction list that was recreated for the fragment, if any.
+    pub fn instructions(&self) -> Option<InstrListRef<'a>> {
+        if self.raw.ilist.is_null() {
+            return None;
+        }
+
+        Some(unsafe { InstrListRef::from_raw(self.context, self.raw.ilist) })
+    }
+}
+
+/// Handler for the deletion of fragments from the code cache.
+pub trait DeleteHandler {
+    /// Invoked when the fragment with the given tag is deleted from the code cache, e.g. because
+    /// it was evicted or because the application code it was built from was modified. The same tag
+    /// may be deleted more than once, as a basic block and a trace may share the same tag, and as a
+    /// fragment may be rebuilt after it was deleted.
+    fn delete_fragment(&mut self, context: &mut DrContext<'_>, tag: BlockTag);
+}
+
+extern "C" fn delete_event<T: DeleteHandler>(
+    context: *mut core::ffi::c_void,
+    tag: *mut core::ffi::c_void,
+    handler: &Mutex<T>,
+) {
+    let mut context = unsafe { DrContext::from_raw(context) };
+    let tag = BlockTag::from_raw(tag);
+
+    if let Ok(mut handler) = handler.lock() {
+        handler.delete_fragment(&mut context, tag);
+    }
+}
+
+pub struct RegisteredDeleteHandler<T: DeleteHandler> {
+    _handler: Arc<Mutex<T>>,
+    closure: Closure,
+}
+
+unsafe impl<T: DeleteHandler> Send for RegisteredDeleteHandler<T> {}
+unsafe impl<T: DeleteHandler> Sync for RegisteredDeleteHandler<T> {}
+
+impl<T: DeleteHandler> Drop for RegisteredDeleteHandler<T> {
+    fn drop(&mut self) {
+        let func: extern "C" fn(*mut core::ffi::c_void, *mut core::ffi::c_void) = unsafe {
+            core::mem::transmute(self.closure.code())
+        };
+
+        unsafe {
+            dr_unregister_delete_event(Some(func));
+        }
+    }
+}
+
+/// Registers a handler for the fragment deletion event.
+pub fn register_delete_handler<T: DeleteHandler>(handler: &Arc<Mutex<T>>) -> RegisteredDeleteHandler<T> {
+    let closure = Closure::new(
+        2,
+        unsafe {
+            core::mem::transmute(delete_event::<T> as extern "C" fn(_, _, _))
+        },
+        Arc::as_ptr(handler) as *mut core::ffi::c_void,
+    );
+
+    let func: extern "C" fn(*mut core::ffi::c_void, *mut core::ffi::c_void) = unsafe {
+        core::mem::transmute(closure.code())
+    };
+
+    unsafe {
+        dr_register_delete_event(Some(func));
+    }
+
+    RegisteredDeleteHandler {
+        _handler: Arc::clone(handler),
+        closure,
+    }
+}
@@ -1,6 +1,9 @@
-use crate::{Operand, SourceOperandIter, TargetOperandIter};
+use crate::{DrContext, Opcode, Operand, SourceOperandIter, TargetOperandIter};
+use core::marker::PhantomData;
 use dynamorio_sys::*;
 
+/// An owned instruction that is destroyed when dropped, unless ownership is transferred to an
+/// instruction list by inserting it.
 #[derive(Debug)]
 pub struct Instruction {
     pub(crate) context: *mut core::ffi::c_void,
@@ -8,7 +11,10 @@ pub struct Instruction {
 }
 
 impl Instruction {
-    pub fn from_raw(context: *mut core::ffi::c_void, raw: *mut instr_t) -> Self {
+    /// # Safety
+    /// The caller must ensure that `raw` points to a valid instruction that is not owned by anyone
+    /// else, as the instruction will be destroyed when the returned value is dropped.
+    pub unsafe fn from_raw(context: *mut core::ffi::c_void, raw: *mut instr_t) -> Self {
         Self {
             context,
             raw,
@@ -19,17 +25,134 @@ impl Instruction {
         self.raw
     }
 
-    pub fn location(&self) -> usize {
+    /// Gives up ownership of the instruction and returns the raw pointer, such that it is no
+    /// longer destroyed when dropped.
+    pub fn into_raw(self) -> *mut instr_t {
+        let raw = self.raw;
+
+        core::mem::forget(self);
+
+        raw
+    }
+
+    /// Returns a borrowed handle to this instruction.
+    pub fn as_instr_ref(&self) -> InstrRef<'_> {
         unsafe {
-            instr_get_app_pc(self.raw) as usize
+            InstrRef::from_raw(self.raw)
         }
     }
+}
 
-    pub fn opcode(&self) -> u32 {
+impl Drop for Instruction {
+    fn drop(&mut self) {
         unsafe {
-            instr_get_opcode(self.raw) as u32
+            instr_destroy(self.context, self.raw);
         }
     }
+}
+
+impl Clone for Instruction {
+    fn clone(&self) -> Self {
+        let raw = unsafe {
+            instr_clone(self.context, self.raw)
+        };
+
+        Self {
+            context: self.context,
+            raw,
+        }
+    }
+}
+
+/// A borrowed handle to an instruction that is owned by someone else, typically an instruction
+/// list. The handle is only valid for the lifetime `'a`, and unlike [`Instruction`], dropping an
+/// `InstrRef` does not destroy the underlying instruction.
+#[derive(Clone, Copy, Debug)]
+pub struct InstrRef<'a> {
+    pub(crate) raw: *mut instr_t,
+    _marker: PhantomData<&'a instr_t>,
+}
+
+impl<'a> InstrRef<'a> {
+    /// # Safety
+    /// The caller must ensure that `raw` points to a valid instruction that outlives `'a`.
+    pub unsafe fn from_raw(raw: *mut instr_t) -> Self {
+        Self {
+            raw,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Wraps the raw pointer, returning `None` if it is null.
+    pub(crate) unsafe fn from_raw_nullable(raw: *mut instr_t) -> Option<Self> {
+        if raw.is_null() {
+            return None;
+        }
+
+        Some(Self::from_raw(raw))
+    }
+
+    pub fn raw(&self) -> *mut instr_t {
+        self.raw
+    }
+
+    /// Takes ownership of an instruction that has been unlinked from its list through
+    /// [`InstrListRef::remove`] or [`InstrListRef::replace`], such that it is destroyed when the
+    /// returned [`Instruction`] is dropped. Unlinked instructions that are not taken over are
+    /// leaked.
+    ///
+    /// # Safety
+    /// The instruction must no longer be part of any list, and neither this handle nor any other
+    /// handle to the instruction may be used afterwards.
+    ///
+    /// [`InstrListRef::remove`]: crate::InstrListRef::remove
+    /// [`InstrListRef::replace`]: crate::InstrListRef::replace
+    pub unsafe fn into_owned(self, context: &DrContext<'_>) -> Instruction {
+        Instruction::from_raw(context.context, self.raw)
+    }
+
+    /// Returns the next instruction in the list, if any.
+    pub fn next(&self) -> Option<InstrRef<'a>> {
+        unsafe {
+            Self::from_raw_nullable(instr_get_next(self.raw))
+        }
+    }
+
+    /// Returns the previous instruction in the list, if any.
+    pub fn prev(&self) -> Option<InstrRef<'a>> {
+        unsafe {
+            Self::from_raw_nullable(instr_get_prev(self.raw))
+        }
+    }
+
+    /// Returns the next application (i.e. non-meta) instruction in the list, if any.
+    pub fn next_app(&self) -> Option<InstrRef<'a>> {
+        unsafe {
+            Self::from_raw_nullable(instr_get_next_app(self.raw))
+        }
+    }
+
+    /// Returns whether this is an application instruction, as opposed to a meta instruction
+    /// inserted by a client.
+    pub fn is_app(&self) -> bool {
+        unsafe {
+            instr_is_app(self.raw) != 0
+        }
+    }
+
+    pub fn location(&self) -> usize {
+        unsafe {
+            instr_get_app_pc(self.raw) as usize
+        }
+    }
+
+    pub fn opcode(&self) -> Opcode {
+        let opcode = unsafe {
+            instr_get_opcode(self.raw)
+        };
+
+        Opcode::from_raw(opcode as u32)
+    }
 
     pub fn is_direct_call(&self) -> bool {
         unsafe {
@@ -67,48 +190,50 @@ impl Instruction {
         }
     }
 
-    pub fn source_operands(&self) -> SourceOperandIter {
+    pub fn source_operands(&self) -> SourceOperandIter<'a> {
         let count = unsafe {
             instr_num_srcs(self.raw)
         } as usize;
 
         SourceOperandIter {
-            instruction: self,
+            instruction: *self,
             index: 0,
             count,
         }
     }
 
-    pub fn target_operands(&self) -> TargetOperandIter {
+    pub fn target_operands(&self) -> TargetOperandIter<'a> {
         let count = unsafe {
             instr_num_dsts(self.raw)
         } as usize;
 
         TargetOperandIter {
-            instruction: self,
+            instruction: *self,
             index: 0,
             count,
         }
     }
 }
 
-impl Drop for Instruction {
-    fn drop(&mut self) {
-        unsafe {
-            instr_destroy(self.context, self.raw);
-        }
-    }
+/// An iterator over the instructions of an instruction list.
+#[derive(Debug)]
+pub struct InstrIter<'a> {
+    pub(crate) next: Option<InstrRef<'a>>,
+    pub(crate) app_only: bool,
 }
 
-impl Clone for Instruction {
-    fn clone(&self) -> Self {
-        let raw = unsafe {
-            instr_clone(self.context, self.raw)
+impl<'a> Iterator for InstrIter<'a> {
+    type Item = InstrRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let instruction = self.next.take()?;
+
+        self.next = if self.app_only {
+            instruction.next_app()
+        } else {
+            instruction.next()
         };
 
-        Self {
-            context: self.context,
-            raw,
-        }
+        Some(instruction)
     }
 }
@@ -1,6 +1,12 @@
-use crate::instruction::Instruction;
+use crate::DrContext;
+#[cfg(target_arch = "x86_64")]
+use crate::Register;
+use crate::instruction::{InstrIter, InstrRef, Instruction};
+use core::marker::PhantomData;
 use dynamorio_sys::*;
 
+/// An owned instruction list. Dropping it destroys the list as well as the instructions it
+/// contains.
 #[derive(Debug)]
 pub struct InstructionList {
     pub(crate) context: *mut core::ffi::c_void,
@@ -8,76 +14,198 @@ pub struct InstructionList {
 }
 
 impl InstructionList {
+    /// Creates a new, empty instruction list.
+    pub fn new(context: &DrContext<'_>) -> Self {
+        let raw = unsafe {
+            instrlist_create(context.context)
+        };
+
+        Self {
+            context: context.context,
+            raw,
+        }
+    }
+
+    /// # Safety
+    /// The caller must ensure that `raw` points to a valid instruction list that is not owned by
+    /// anyone else, as the list will be destroyed when the returned value is dropped.
+    pub unsafe fn from_raw(context: *mut core::ffi::c_void, raw: *mut instrlist_t) -> Self {
+        Self {
+            context,
+            raw,
+        }
+    }
+
     pub fn raw(&self) -> *mut instrlist_t {
         self.raw
     }
 
-    pub fn from_raw(context: *mut core::ffi::c_void, raw: *mut instrlist_t) -> Self {
+    /// Returns a borrowed handle to this instruction list that can be used to inspect and modify
+    /// it.
+    pub fn as_list_ref(&mut self) -> InstrListRef<'_> {
+        unsafe {
+            InstrListRef::from_raw(self.context, self.raw)
+        }
+    }
+}
+
+impl Drop for InstructionList {
+    fn drop(&mut self) {
+        unsafe {
+            instrlist_clear_and_destroy(self.context, self.raw);
+        }
+    }
+}
+
+impl Clone for InstructionList {
+    fn clone(&self) -> Self {
+        let raw = unsafe {
+            instrlist_clone(self.context, self.raw)
+        };
+
+        Self {
+            context: self.context,
+            raw,
+        }
+    }
+}
+
+/// A borrowed handle to an instruction list that is owned by someone else, such as the basic
+/// block passed to the instrumentation events. The handle is only valid for the lifetime `'a`,
+/// and dropping it does not destroy the list.
+#[derive(Debug)]
+pub struct InstrListRef<'a> {
+    pub(crate) context: *mut core::ffi::c_void,
+    pub(crate) raw: *mut instrlist_t,
+    _marker: PhantomData<&'a mut instrlist_t>,
+}
+
+impl<'a> InstrListRef<'a> {
+    /// # Safety
+    /// The caller must ensure that `raw` points to a valid instruction list that outlives `'a`.
+    pub unsafe fn from_raw(context: *mut core::ffi::c_void, raw: *mut instrlist_t) -> Self {
         Self {
             context,
             raw,
+            _marker: PhantomData,
         }
     }
 
+    pub fn raw(&self) -> *mut instrlist_t {
+        self.raw
+    }
+
+    /// Returns the first instruction in the list, if any.
+    pub fn first(&self) -> Option<InstrRef<'a>> {
+        unsafe {
+            InstrRef::from_raw_nullable(instrlist_first(self.raw))
+        }
+    }
+
+    /// Returns the last instruction in the list, if any.
+    pub fn last(&self) -> Option<InstrRef<'a>> {
+        unsafe {
+            InstrRef::from_raw_nullable(instrlist_last(self.raw))
+        }
+    }
+
+    /// Returns the first application (i.e. non-meta) instruction in the list, if any.
+    pub fn first_app(&self) -> Option<InstrRef<'a>> {
+        unsafe {
+            InstrRef::from_raw_nullable(instrlist_first_app(self.raw))
+        }
+    }
+
+    /// Returns an iterator over all the instructions in the list, including meta instructions.
+    /// The list may be modified while iterating, in which case instructions inserted after the
+    /// current instruction are skipped.
+    pub fn iter(&self) -> InstrIter<'a> {
+        InstrIter {
+            next: self.first(),
+            app_only: false,
+        }
+    }
+
+    /// Returns an iterator over the application (i.e. non-meta) instructions in the list.
+    pub fn iter_app(&self) -> InstrIter<'a> {
+        InstrIter {
+            next: self.first_app(),
+            app_only: true,
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
     pub fn save_register(
         &mut self,
-        anchor: &Instruction,
-        register: reg_id_t,
+        anchor: InstrRef<'_>,
+        register: Register,
         slot: dr_spill_slot_t,
     ) {
         unsafe {
-            dr_save_reg(self.context, self.raw, anchor.raw, register, slot);
+            dr_save_reg(self.context, self.raw, anchor.raw, register.raw(), slot);
         }
     }
 
+    #[cfg(target_arch = "x86_64")]
     pub fn restore_register(
         &mut self,
-        anchor: &Instruction,
-        register: reg_id_t,
+        anchor: InstrRef<'_>,
+        register: Register,
         slot: dr_spill_slot_t,
     ) {
         unsafe {
-            dr_restore_reg(self.context, self.raw, anchor.raw, register, slot);
+            dr_restore_reg(self.context, self.raw, anchor.raw, register.raw(), slot);
         }
     }
 
-    pub fn insert_before(
+    /// Appends the instruction to the end of the list, which takes over ownership of it.
+    pub fn append(
         &mut self,
-        anchor: &Instruction,
         instruction: Instruction,
     ) {
         unsafe {
-            instrlist_preinsert(self.raw, anchor.raw, instruction.raw);
+            instrlist_append(self.raw, instruction.into_raw());
         }
+    }
 
-        core::mem::forget(instruction);
+    pub fn insert_before(
+        &mut self,
+        anchor: InstrRef<'_>,
+        instruction: Instruction,
+    ) {
+        unsafe {
+            instrlist_preinsert(self.raw, anchor.raw, instruction.into_raw());
+        }
     }
 
     pub fn insert_after(
         &mut self,
-        anchor: &Instruction,
+        anchor: InstrRef<'_>,
         instruction: Instruction,
     ) {
         unsafe {
-            instrlist_postinsert(self.raw, anchor.raw, instruction.raw);
+            instrlist_postinsert(self.raw, anchor.raw, instruction.into_raw());
         }
-
-        core::mem::forget(instruction);
     }
 
+    /// Replaces `old_instruction` with `instruction`, which is now owned by the list. The old
+    /// instruction is unlinked from the list, but not destroyed. Use [`InstrRef::into_owned`] to
+    /// destroy it.
     pub fn replace(
         &mut self,
-        old_instruction: &Instruction,
+        old_instruction: InstrRef<'_>,
         instruction: Instruction,
     ) {
         unsafe {
-            instrlist_replace(self.raw, old_instruction.raw, instruction.raw);
+            instrlist_replace(self.raw, old_instruction.raw, instruction.into_raw());
         }
     }
 
+    /// Unlinks the instruction from the list without destroying it. Use [`InstrRef::into_owned`]
+    /// to destroy it.
     pub fn remove(
         &mut self,
-        instruction: &Instruction,
+        instruction: InstrRef<'_>,
     ) {
         unsafe {
             instrlist_remove(self.raw, instruction.raw);
@@ -86,7 +214,7 @@ impl InstructionList {
 
     pub fn insert_clean_call(
         &mut self,
-        anchor: &Instruction,
+        anchor: InstrRef<'_>,
         func: extern "C" fn() -> (),
         save_fpstate: bool,
     ) {
@@ -104,7 +232,7 @@ impl InstructionList {
 
     pub fn insert_call_instrumentation(
         &mut self,
-        anchor: &Instruction,
+        anchor: InstrRef<'_>,
         func: extern "C" fn (usize, usize) -> (),
     ) {
         unsafe {
@@ -119,7 +247,7 @@ impl InstructionList {
 
     pub fn insert_mbr_instrumentation(
         &mut self,
-        anchor: &Instruction,
+        anchor: InstrRef<'_>,
         func: extern "C" fn (usize, usize) -> (),
         spill_slot: dr_spill_slot_t,
     ) {
@@ -136,23 +264,11 @@ impl InstructionList {
 
 }
 
-impl Drop for InstructionList {
-    fn drop(&mut self) {
-        unsafe {
-            instrlist_destroy(self.context, self.raw);
-        }
-    }
-}
-
-impl Clone for InstructionList {
-    fn clone(&self) -> Self {
-        let raw = unsafe {
-            instrlist_clone(self.context, self.raw)
-        };
+impl<'a> IntoIterator for &InstrListRef<'a> {
+    type Item = InstrRef<'a>;
+    type IntoIter = InstrIter<'a>;
 
-        Self {
-            context: self.context,
-            raw,
-        }
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
     }
 }
@@ -7,11 +7,14 @@ pub mod arch;
 pub mod closure;
 pub mod context;
 pub mod event;
+pub mod fragment;
 pub mod instruction;
 pub mod instruction_list;
 pub mod mcontext;
 pub mod module;
+pub mod opcode;
 pub mod operand;
+pub mod trace;
 
 #[cfg(feature = "mgr")]
 pub mod manager;
@@ -34,18 +37,29 @@ use dynamorio_sys::*;
 
 pub use paste::paste;
 
-pub use context::{AfterSyscallContext, BeforeSyscallContext, Context};
+#[cfg(target_arch = "x86_64")]
+pub use arch::Register;
+pub use context::{AfterSyscallContext, BeforeSyscallContext, CurrentContext, DrContext, RedirectError};
 pub use dynamorio_sys::{
     dr_emit_flags_t,
     dr_spill_slot_t,
     process_id_t,
 };
 pub use event::*;
-pub use instruction::Instruction;
-pub use instruction_list::InstructionList;
+pub use fragment::{
+    register_delete_handler,
+    BlockTag,
+    DeleteHandler,
+    FaultFragmentInfo,
+    RegisteredDeleteHandler,
+};
+pub use instruction::{InstrIter, InstrRef, Instruction};
+pub use instruction_list::{InstrListRef, InstructionList};
 pub use mcontext::MachineContext;
 pub use module::ModuleData;
+pub use opcode::Opcode;
 pub use operand::{Operand, SourceOperandIter, TargetOperandIter};
+pub use trace::*;
 
 #[cfg(feature = "mgr")]
 pub use manager::*;
@@ -69,7 +83,13 @@ pub use extension::Extension;
 pub static _USES_DR_VERSION_: i32 = dynamorio_sys::_USES_DR_VERSION_;
 
 /// We need to define `_DR_CLIENT_AVX512_CODE_IN_USE` as DynamoRIO checks this symbol to determine
-/// whether AVX-512 is being used or not.
+/// whether AVX-512 is being used or not. Clients that use AVX-512 instructions or access the upper
+/// zmm and opmask state should enable the `avx512` feature.
+#[cfg(feature = "avx512")]
+#[no_mangle]
+pub static _DR_CLIENT_AVX512_CODE_IN_USE: i8 = 1;
+
+#[cfg(not(feature = "avx512"))]
 #[no_mangle]
 pub static _DR_CLIENT_AVX512_CODE_IN_USE: i8 = dynamorio_sys::_DR_CLIENT_AVX512_CODE_IN_USE_;
 
@@ -1,141 +1,485 @@
-use crate::{Context, Instruction, InstructionList, Manager};
+use crate::{BlockTag, DrContext, InstrListRef, InstrRef, Manager, Priority};
 use crate::closure::Closure;
+use crate::manager::priority::raw_priority_ptr;
+use alloc::boxed::Box;
 use dynamorio_sys::*;
 use drstd::sync::{Arc, Mutex};
 
+type App2AppFunc = extern "C" fn(*mut core::ffi::c_void, *mut core::ffi::c_void, *mut instrlist_t, i8, i8, *mut *mut core::ffi::c_void) -> dr_emit_flags_t;
+type AnalysisFunc = extern "C" fn(*mut core::ffi::c_void, *mut core::ffi::c_void, *mut instrlist_t, i8, i8, *mut core::ffi::c_void) -> dr_emit_flags_t;
+type InsertionFunc = extern "C" fn(*mut core::ffi::c_void, *mut core::ffi::c_void, *mut instrlist_t, *mut instr_t, i8, i8, *mut core::ffi::c_void) -> dr_emit_flags_t;
+type Instru2InstruFunc = AnalysisFunc;
+type DeleteFunc = extern "C" fn(*mut core::ffi::c_void, *mut core::ffi::c_void);
+
+/// Handler for the four phases of drmgr's basic block pipeline. For every basic block, drmgr
+/// first invokes [`BasicBlockHandler::app2app`], then [`BasicBlockHandler::analyse`], then
+/// [`BasicBlockHandler::instrument`] once for every instruction, and finally
+/// [`BasicBlockHandler::instru2instru`].
 pub trait BasicBlockHandler {
+    /// Per-block data that is returned by [`BasicBlockHandler::analyse`] and passed to every
+    /// invocation of [`BasicBlockHandler::instrument`] for the same basic block. The data is
+    /// allocated and freed by the crate, and is dropped once the last instruction has been
+    /// instrumented.
+    type BlockData;
+
+    /// The application-to-application transformation phase, in which the handler may rewrite the
+    /// application code itself, e.g. to expand rep-string instructions into loops.
+    fn app2app(
+        &mut self,
+        _context: &mut DrContext<'_>,
+        _tag: BlockTag,
+        _bb: &mut InstrListRef<'_>,
+        _for_trace: bool,
+        _translating: bool,
+    ) -> dr_emit_flags_t {
+        dr_emit_flags_t::DR_EMIT_DEFAULT
+    }
+
     fn analyse(
         &mut self,
-        context: &mut Context,
-        bb: &InstructionList,
+        context: &mut DrContext<'_>,
+        tag: BlockTag,
+        bb: &InstrListRef<'_>,
         for_trace: bool,
         translating: bool,
-    ) -> dr_emit_flags_t;
+    ) -> (dr_emit_flags_t, Self::BlockData);
 
+    #[allow(clippy::too_many_arguments)]
     fn instrument(
         &mut self,
-        context: &mut Context,
-        bb: &mut InstructionList,
-        instruction: &Instruction,
+        context: &mut DrContext<'_>,
+        tag: BlockTag,
+        bb: &mut InstrListRef<'_>,
+        instruction: InstrRef<'_>,
+        block_data: &Self::BlockData,
+        for_trace: bool,
+        translating: bool,
+    ) -> dr_emit_flags_t;
+
+    /// The instrumentation-to-instrumentation transformation phase, in which the handler may
+    /// optimize the instrumentation that was inserted during the previous phase.
+    fn instru2instru(
+        &mut self,
+        _context: &mut DrContext<'_>,
+        _tag: BlockTag,
+        _bb: &mut InstrListRef<'_>,
+        _for_trace: bool,
+        _translating: bool,
+    ) -> dr_emit_flags_t {
+        dr_emit_flags_t::DR_EMIT_DEFAULT
+    }
+
+    /// Whether [`BasicBlockHandler::delete_block`] should be invoked. The delete event is only
+    /// registered for handlers that opt in, as it is invoked for every fragment that is deleted.
+    const DELETE_BLOCK: bool = false;
+
+    /// Invoked when the fragment with the given tag is deleted from the code cache, such that any
+    /// data the handler keeps per tag can be freed, if [`BasicBlockHandler::DELETE_BLOCK`] is set.
+    /// See [`crate::DeleteHandler::delete_fragment`].
+    fn delete_block(&mut self, _context: &mut DrContext<'_>, _tag: BlockTag) {
+    }
+}
+
+/// Like [`BasicBlockHandler`], but the handler is shared between all application threads and is
+/// invoked through a shared reference rather than through a global lock. This allows basic blocks
+/// to be built concurrently, with the handler using its own, more fine-grained synchronization.
+pub trait SyncBasicBlockHandler {
+    /// See [`BasicBlockHandler::BlockData`].
+    type BlockData;
+
+    /// See [`BasicBlockHandler::app2app`].
+    fn app2app(
+        &self,
+        _context: &mut DrContext<'_>,
+        _tag: BlockTag,
+        _bb: &mut InstrListRef<'_>,
+        _for_trace: bool,
+        _translating: bool,
+    ) -> dr_emit_flags_t {
+        dr_emit_flags_t::DR_EMIT_DEFAULT
+    }
+
+    fn analyse(
+        &self,
+        context: &mut DrContext<'_>,
+        tag: BlockTag,
+        bb: &InstrListRef<'_>,
+        for_trace: bool,
+        translating: bool,
+    ) -> (dr_emit_flags_t, Self::BlockData);
+
+    #[allow(clippy::too_many_arguments)]
+    fn instrument(
+        &self,
+        context: &mut DrContext<'_>,
+        tag: BlockTag,
+        bb: &mut InstrListRef<'_>,
+        instruction: InstrRef<'_>,
+        block_data: &Self::BlockData,
         for_trace: bool,
         translating: bool,
     ) -> dr_emit_flags_t;
+
+    /// See [`BasicBlockHandler::instru2instru`].
+    fn instru2instru(
+        &self,
+        _context: &mut DrContext<'_>,
+        _tag: BlockTag,
+        _bb: &mut InstrListRef<'_>,
+        _for_trace: bool,
+        _translating: bool,
+    ) -> dr_emit_flags_t {
+        dr_emit_flags_t::DR_EMIT_DEFAULT
+    }
+
+    /// See [`BasicBlockHandler::DELETE_BLOCK`].
+    const DELETE_BLOCK: bool = false;
+
+    /// See [`BasicBlockHandler::delete_block`].
+    fn delete_block(&self, _context: &mut DrContext<'_>, _tag: BlockTag) {
+    }
 }
 
-extern "C" fn bb_analysis_event<T: BasicBlockHandler>(
+impl<T: BasicBlockHandler> SyncBasicBlockHandler for Mutex<T> {
+    const DELETE_BLOCK: bool = T::DELETE_BLOCK;
+
+    // The block data is absent if the lock could not be acquired during the analysis phase, in
+    // which case the instrumentation phase is skipped as well.
+    type BlockData = Option<T::BlockData>;
+
+    fn app2app(
+        &self,
+        context: &mut DrContext<'_>,
+        tag: BlockTag,
+        bb: &mut InstrListRef<'_>,
+        for_trace: bool,
+        translating: bool,
+    ) -> dr_emit_flags_t {
+        match self.lock() {
+            Ok(mut handler) => handler.app2app(context, tag, bb, for_trace, translating),
+            _ => dr_emit_flags_t::DR_EMIT_DEFAULT,
+        }
+    }
+
+    fn analyse(
+        &self,
+        context: &mut DrContext<'_>,
+        tag: BlockTag,
+        bb: &InstrListRef<'_>,
+        for_trace: bool,
+        translating: bool,
+    ) -> (dr_emit_flags_t, Self::BlockData) {
+        match self.lock() {
+            Ok(mut handler) => {
+                let (flags, data) = handler.analyse(context, tag, bb, for_trace, translating);
+
+                (flags, Some(data))
+            }
+            _ => (dr_emit_flags_t::DR_EMIT_DEFAULT, None),
+        }
+    }
+
+    fn instrument(
+        &self,
+        context: &mut DrContext<'_>,
+        tag: BlockTag,
+        bb: &mut InstrListRef<'_>,
+        instruction: InstrRef<'_>,
+        block_data: &Self::BlockData,
+        for_trace: bool,
+        translating: bool,
+    ) -> dr_emit_flags_t {
+        let block_data = match block_data {
+            Some(block_data) => block_data,
+            _ => return dr_emit_flags_t::DR_EMIT_DEFAULT,
+        };
+
+        match self.lock() {
+            Ok(mut handler) => handler.instrument(context, tag, bb, instruction, block_data, for_trace, translating),
+            _ => dr_emit_flags_t::DR_EMIT_DEFAULT,
+        }
+    }
+
+    fn instru2instru(
+        &self,
+        context: &mut DrContext<'_>,
+        tag: BlockTag,
+        bb: &mut InstrListRef<'_>,
+        for_trace: bool,
+        translating: bool,
+    ) -> dr_emit_flags_t {
+        match self.lock() {
+            Ok(mut handler) => handler.instru2instru(context, tag, bb, for_trace, translating),
+            _ => dr_emit_flags_t::DR_EMIT_DEFAULT,
+        }
+    }
+
+    fn delete_block(&self, context: &mut DrContext<'_>, tag: BlockTag) {
+        if let Ok(mut handler) = self.lock() {
+            handler.delete_block(context, tag);
+        }
+    }
+}
+
+extern "C" fn bb_app2app_event<T: SyncBasicBlockHandler>(
     context: *mut core::ffi::c_void,
-    _tag: *mut core::ffi::c_void,
+    tag: *mut core::ffi::c_void,
     bb: *mut instrlist_t,
     for_trace: i8,
     translating: i8,
-    _user_data: *mut *mut core::ffi::c_void,
-    handler: &Mutex<T>,
+    user_data: *mut *mut core::ffi::c_void,
+    handler: &T,
 ) -> dr_emit_flags_t {
-    let bb = InstructionList::from_raw(context, bb);
+    // Allocate the storage for the block data, which is passed along to the other phases and
+    // filled in by the analysis phase.
+    let block_data: Box<Option<T::BlockData>> = Box::new(None);
+
+    unsafe {
+        *user_data = Box::into_raw(block_data) as *mut core::ffi::c_void;
+    }
+
+    let mut bb = unsafe { InstrListRef::from_raw(context, bb) };
     let for_trace = for_trace != 0;
     let translating = translating != 0;
-    let mut context = Context::from_raw(context);
-    let mut flags = dr_emit_flags_t::DR_EMIT_DEFAULT;
+    let tag = BlockTag::from_raw(tag);
+    let mut context = unsafe { DrContext::from_raw(context) };
 
-    if let Ok(mut handler) = handler.lock() {
-        flags = handler.analyse(&mut context, &bb, for_trace, translating);
-    }
+    handler.app2app(&mut context, tag, &mut bb, for_trace, translating)
+}
+
+extern "C" fn bb_analysis_event<T: SyncBasicBlockHandler>(
+    context: *mut core::ffi::c_void,
+    tag: *mut core::ffi::c_void,
+    bb: *mut instrlist_t,
+    for_trace: i8,
+    translating: i8,
+    user_data: *mut core::ffi::c_void,
+    handler: &T,
+) -> dr_emit_flags_t {
+    let block_data = unsafe { &mut *(user_data as *mut Option<T::BlockData>) };
+    let bb = unsafe { InstrListRef::from_raw(context, bb) };
+    let for_trace = for_trace != 0;
+    let translating = translating != 0;
+    let tag = BlockTag::from_raw(tag);
+    let mut context = unsafe { DrContext::from_raw(context) };
+    let (flags, data) = handler.analyse(&mut context, tag, &bb, for_trace, translating);
 
-    core::mem::forget(context);
-    core::mem::forget(bb);
+    *block_data = Some(data);
 
     flags
 }
 
-extern "C" fn bb_instrumentation_event<T: BasicBlockHandler>(
+extern "C" fn bb_instrumentation_event<T: SyncBasicBlockHandler>(
     context: *mut core::ffi::c_void,
-    _tag: *mut core::ffi::c_void,
+    tag: *mut core::ffi::c_void,
     bb: *mut instrlist_t,
     instr: *mut instr_t,
     for_trace: i8,
     translating: i8,
-    _user_data: *mut core::ffi::c_void,
-    handler: &Mutex<T>,
+    user_data: *mut core::ffi::c_void,
+    handler: &T,
 ) -> dr_emit_flags_t {
-    let mut bb = InstructionList::from_raw(context, bb);
-    let instr = Instruction::from_raw(context, instr);
+    let block_data = match unsafe { &*(user_data as *const Option<T::BlockData>) } {
+        Some(block_data) => block_data,
+        _ => return dr_emit_flags_t::DR_EMIT_DEFAULT,
+    };
+
+    let mut bb = unsafe { InstrListRef::from_raw(context, bb) };
+    let instr = unsafe { InstrRef::from_raw(instr) };
     let for_trace = for_trace != 0;
     let translating = translating != 0;
-    let mut context = Context::from_raw(context);
-    let mut flags = dr_emit_flags_t::DR_EMIT_DEFAULT;
+    let tag = BlockTag::from_raw(tag);
+    let mut context = unsafe { DrContext::from_raw(context) };
 
-    if let Ok(mut handler) = handler.lock() {
-        flags = handler.instrument(&mut context, &mut bb, &instr, for_trace, translating);
-    }
+    handler.instrument(&mut context, tag, &mut bb, instr, block_data, for_trace, translating)
+}
+
+extern "C" fn bb_instru2instru_event<T: SyncBasicBlockHandler>(
+    context: *mut core::ffi::c_void,
+    tag: *mut core::ffi::c_void,
+    bb: *mut instrlist_t,
+    for_trace: i8,
+    translating: i8,
+    user_data: *mut core::ffi::c_void,
+    handler: &T,
+) -> dr_emit_flags_t {
+    let mut bb = unsafe { InstrListRef::from_raw(context, bb) };
+    let for_trace = for_trace != 0;
+    let translating = translating != 0;
+    let tag = BlockTag::from_raw(tag);
+    let mut context = unsafe { DrContext::from_raw(context) };
+    let flags = handler.instru2instru(&mut context, tag, &mut bb, for_trace, translating);
 
-    core::mem::forget(context);
-    core::mem::forget(bb);
-    core::mem::forget(instr);
+    // This is the last phase, so the block data is no longer needed.
+    if !user_data.is_null() {
+        drop(unsafe { Box::from_raw(user_data as *mut Option<T::BlockData>) });
+    }
 
     flags
 }
 
-pub struct RegisteredBasicBlockHandler<T: BasicBlockHandler> {
-    _handler: Arc<Mutex<T>>,
+extern "C" fn bb_delete_event<T: SyncBasicBlockHandler>(
+    context: *mut core::ffi::c_void,
+    tag: *mut core::ffi::c_void,
+    handler: &T,
+) {
+    let tag = BlockTag::from_raw(tag);
+    let mut context = unsafe { DrContext::from_raw(context) };
+
+    handler.delete_block(&mut context, tag);
+}
+
+pub struct RegisteredSyncBasicBlockHandler<T: SyncBasicBlockHandler> {
+    _handler: Arc<T>,
+    bb_app2app_closure: Closure,
     bb_analysis_closure: Closure,
-    _bb_instrumentation_closure: Closure,
+    bb_instrumentation_closure: Closure,
+    bb_instru2instru_closure: Closure,
+    bb_delete_closure: Option<Closure>,
+    _priority: Option<Priority>,
 }
 
-unsafe impl<T: BasicBlockHandler> Send for RegisteredBasicBlockHandler<T> {}
-unsafe impl<T: BasicBlockHandler> Sync for RegisteredBasicBlockHandler<T> {}
+pub type RegisteredBasicBlockHandler<T> = RegisteredSyncBasicBlockHandler<Mutex<T>>;
+
+unsafe impl<T: SyncBasicBlockHandler> Send for RegisteredSyncBasicBlockHandler<T> {}
+unsafe impl<T: SyncBasicBlockHandler> Sync for RegisteredSyncBasicBlockHandler<T> {}
 
-impl<T: BasicBlockHandler> Drop for RegisteredBasicBlockHandler<T> {
+impl<T: SyncBasicBlockHandler> RegisteredSyncBasicBlockHandler<T> {
+    fn callbacks(&self) -> (App2AppFunc, AnalysisFunc, InsertionFunc, Instru2InstruFunc) {
+        unsafe {
+            (
+                core::mem::transmute::<*mut core::ffi::c_void, App2AppFunc>(self.bb_app2app_closure.code()),
+                core::mem::transmute::<*mut core::ffi::c_void, AnalysisFunc>(self.bb_analysis_closure.code()),
+                core::mem::transmute::<*mut core::ffi::c_void, InsertionFunc>(self.bb_instrumentation_closure.code()),
+                core::mem::transmute::<*mut core::ffi::c_void, Instru2InstruFunc>(self.bb_instru2instru_closure.code()),
+            )
+        }
+    }
+}
+
+impl<T: SyncBasicBlockHandler> Drop for RegisteredSyncBasicBlockHandler<T> {
     fn drop(&mut self) {
-        let bb_analysis_wrapper: extern "C" fn(*mut core::ffi::c_void, *mut core::ffi::c_void, *mut instrlist_t, i8, i8, *mut *mut core::ffi::c_void) -> dr_emit_flags_t = unsafe {
-            core::mem::transmute(self.bb_analysis_closure.code())
-        };
+        let (app2app, analysis, instrumentation, instru2instru) = self.callbacks();
 
         unsafe {
-            drmgr_unregister_bb_instrumentation_event(
-                Some(bb_analysis_wrapper),
+            drmgr_unregister_bb_instrumentation_ex_event(
+                Some(app2app),
+                Some(analysis),
+                Some(instrumentation),
+                Some(instru2instru),
             );
         }
+
+        if let Some(bb_delete_closure) = &self.bb_delete_closure {
+            let delete: DeleteFunc = unsafe {
+                core::mem::transmute(bb_delete_closure.code())
+            };
+
+            unsafe {
+                dr_unregister_delete_event(Some(delete));
+            }
+        }
     }
 }
 
 impl Manager {
-    pub fn instrument_basic_block<T: BasicBlockHandler>(&self, handler: &Arc<Mutex<T>>) -> RegisteredBasicBlockHandler<T> {
+    pub fn instrument_basic_block<T: BasicBlockHandler>(
+        &self,
+        handler: &Arc<Mutex<T>>,
+        priority: Option<&Priority>,
+    ) -> RegisteredBasicBlockHandler<T> {
+        self.instrument_basic_block_inner(handler, priority)
+    }
+
+    /// Registers a basic block handler that is shared between all application threads without
+    /// being wrapped in a [`Mutex`], such that the handler is responsible for its own
+    /// synchronization.
+    pub fn instrument_basic_block_sync<T: SyncBasicBlockHandler + Send + Sync>(
+        &self,
+        handler: &Arc<T>,
+        priority: Option<&Priority>,
+    ) -> RegisteredSyncBasicBlockHandler<T> {
+        self.instrument_basic_block_inner(handler, priority)
+    }
+
+    fn instrument_basic_block_inner<T: SyncBasicBlockHandler>(
+        &self,
+        handler: &Arc<T>,
+        priority: Option<&Priority>,
+    ) -> RegisteredSyncBasicBlockHandler<T> {
+        let bb_app2app_closure = Closure::new(
+            6,
+            unsafe {
+                core::mem::transmute(bb_app2app_event::<T> as unsafe extern "C" fn(_, _, _, _, _, _, _) -> _)
+            },
+            Arc::as_ptr(handler) as *mut core::ffi::c_void,
+        );
+
         let bb_analysis_closure = Closure::new(
             6,
             unsafe {
                 core::mem::transmute(bb_analysis_event::<T> as unsafe extern "C" fn(_, _, _, _, _, _, _) -> _)
             },
-            Arc::as_ptr(&handler) as *mut core::ffi::c_void,
+            Arc::as_ptr(handler) as *mut core::ffi::c_void,
         );
 
-        let bb_analysis_wrapper: extern "C" fn(*mut core::ffi::c_void, *mut core::ffi::c_void, *mut instrlist_t, i8, i8, *mut *mut core::ffi::c_void) -> dr_emit_flags_t = unsafe {
-            core::mem::transmute(bb_analysis_closure.code())
-        };
-
         let bb_instrumentation_closure = Closure::new(
             7,
             unsafe {
                 core::mem::transmute(bb_instrumentation_event::<T> as unsafe extern "C" fn(_, _, _, _, _, _, _, _) -> _)
             },
-            Arc::as_ptr(&handler) as *mut core::ffi::c_void,
+            Arc::as_ptr(handler) as *mut core::ffi::c_void,
+        );
+
+        let bb_instru2instru_closure = Closure::new(
+            6,
+            unsafe {
+                core::mem::transmute(bb_instru2instru_event::<T> as unsafe extern "C" fn(_, _, _, _, _, _, _) -> _)
+            },
+            Arc::as_ptr(handler) as *mut core::ffi::c_void,
         );
 
-        let bb_instrumentation_wrapper: extern "C" fn(*mut core::ffi::c_void, *mut core::ffi::c_void, *mut instrlist_t, *mut instr_t, i8, i8, *mut core::ffi::c_void) -> dr_emit_flags_t = unsafe {
-            core::mem::transmute(bb_instrumentation_closure.code())
+        let bb_delete_closure = T::DELETE_BLOCK.then(|| Closure::new(
+            2,
+            unsafe {
+                core::mem::transmute(bb_delete_event::<T> as extern "C" fn(_, _, _))
+            },
+            Arc::as_ptr(handler) as *mut core::ffi::c_void,
+        ));
+
+        let registered = RegisteredSyncBasicBlockHandler {
+            _handler: Arc::clone(handler),
+            bb_app2app_closure,
+            bb_analysis_closure,
+            bb_instrumentation_closure,
+            bb_instru2instru_closure,
+            bb_delete_closure,
+            _priority: priority.cloned(),
         };
 
+        let (app2app, analysis, instrumentation, instru2instru) = registered.callbacks();
+        let mut raw_priority = registered._priority.as_ref().map(Priority::raw);
+
         unsafe {
-            drmgr_register_bb_instrumentation_event(
-                Some(bb_analysis_wrapper),
-                Some(bb_instrumentation_wrapper),
-                core::ptr::null_mut(),
+            drmgr_register_bb_instrumentation_ex_event(
+                Some(app2app),
+                Some(analysis),
+                Some(instrumentation),
+                Some(instru2instru),
+                raw_priority_ptr(&mut raw_priority),
             );
         }
 
-        RegisteredBasicBlockHandler {
-            _handler: Arc::clone(&handler),
-            bb_analysis_closure,
-            _bb_instrumentation_closure: bb_instrumentation_closure,
+        if let Some(bb_delete_closure) = &registered.bb_delete_closure {
+            let delete: DeleteFunc = unsafe {
+                core::mem::transmute(bb_delete_closure.code())
+            };
+
+            unsafe {
+                dr_register_delete_event(Some(delete));
+            }
         }
+
+        registered
     }
 }
@@ -0,0 +1,388 @@
+use crate::{ExitHandler, Manager, Priority};
+use crate::closure::Closure;
+use crate::manager::priority::{insertion_index, raw_priority_ptr};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use drstd::sync::{Arc, Mutex, Once};
+use dynamorio_sys::*;
+
+#[cfg(target_os = "linux")]
+use crate::DrContext;
+#[cfg(target_os = "linux")]
+use core::sync::atomic::AtomicBool;
+
+type ExitFunc = extern "C" fn();
+#[cfg(target_os = "linux")]
+type PreExitFunc = extern "C" fn(*mut core::ffi::c_void);
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+const SYS_EXIT_GROUP: i32 = 231;
+#[cfg(all(target_os = "linux", target_arch = "x86"))]
+const SYS_EXIT_GROUP: i32 = 252;
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+const SYS_EXIT_GROUP: i32 = 94;
+#[cfg(all(target_os = "linux", target_arch = "arm"))]
+const SYS_EXIT_GROUP: i32 = 248;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+static EXIT_CALLBACKS: Once<OrderedCallbacks<ExitFunc>> = Once::new();
+#[cfg(target_os = "linux")]
+static PRE_EXIT_CALLBACKS: Once<OrderedCallbacks<PreExitFunc>> = Once::new();
+#[cfg(target_os = "linux")]
+static PRE_EXIT_PRIORITY: Once<Priority> = Once::new();
+#[cfg(target_os = "linux")]
+static PRE_EXIT_INVOKED: AtomicBool = AtomicBool::new(false);
+
+/// Functions that register and unregister the events that invoke a list of callbacks.
+type Hooks = (fn(), fn());
+
+struct Entry<F> {
+    id: usize,
+    priority: Priority,
+    func: F,
+}
+
+/// A list of callbacks that is kept ordered by priority, for events that DynamoRIO does not order
+/// through drmgr.
+struct OrderedCallbacks<F> {
+    entries: Mutex<Vec<Entry<F>>>,
+    /// The hooks are invoked when the first callback is inserted and when the last callback is
+    /// removed respectively.
+    hooks: Option<Hooks>,
+}
+
+impl<F: Copy> OrderedCallbacks<F> {
+    fn new(hooks: Option<Hooks>) -> Self {
+        Self {
+            entries: Mutex::new(Vec::new()),
+            hooks,
+        }
+    }
+
+    fn insert(&self, priority: Priority, func: F) -> Option<usize> {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let mut entries = self.entries.lock().ok()?;
+        let index = insertion_index(entries.iter().map(|entry| &entry.priority), &priority)?;
+
+        if entries.is_empty() {
+            if let Some((attach, _)) = self.hooks {
+                attach();
+            }
+        }
+
+        entries.insert(index, Entry {
+            id,
+            priority,
+            func,
+        });
+
+        Some(id)
+    }
+
+    fn remove(&self, id: usize) {
+        if let Ok(mut entries) = self.entries.lock() {
+            let len = entries.len();
+
+            entries.retain(|entry| entry.id != id);
+
+            if entries.len() < len && entries.is_empty() {
+                if let Some((_, detach)) = self.hooks {
+                    detach();
+                }
+            }
+        }
+    }
+
+    /// Invokes the callbacks in order. The lock is not held while a callback runs, such that
+    /// callbacks may unregister themselves or others, in which case the unregistered callbacks
+    /// are skipped.
+    fn invoke(&self, mut invoke: impl FnMut(F)) {
+        let ids = match self.entries.lock() {
+            Ok(entries) => entries.iter().map(|entry| entry.id).collect::<Vec<usize>>(),
+            _ => return,
+        };
+
+        for id in ids {
+            let func = match self.entries.lock() {
+                Ok(entries) => entries.iter().find(|entry| entry.id == id).map(|entry| entry.func),
+                _ => None,
+            };
+
+            if let Some(func) = func {
+                invoke(func);
+            }
+        }
+    }
+}
+
+fn exit_callbacks() -> &'static OrderedCallbacks<ExitFunc> {
+    EXIT_CALLBACKS.call_once(|| {
+        unsafe {
+            dr_register_exit_event(Some(exit_event));
+        }
+
+        OrderedCallbacks::new(None)
+    })
+}
+
+extern "C" fn exit_event() {
+    if let Some(callbacks) = EXIT_CALLBACKS.get() {
+        callbacks.invoke(|func| func());
+    }
+}
+
+extern "C" fn ordered_exit_event<T: ExitHandler>(
+    handler: &Mutex<T>,
+) {
+    if let Ok(mut handler) = handler.lock() {
+        handler.exit()
+    }
+}
+
+pub struct RegisteredOrderedExitHandler<T: ExitHandler> {
+    _handler: Arc<Mutex<T>>,
+    id: usize,
+    _closure: Closure,
+}
+
+unsafe impl<T: ExitHandler> Send for RegisteredOrderedExitHandler<T> {}
+unsafe impl<T: ExitHandler> Sync for RegisteredOrderedExitHandler<T> {}
+
+impl<T: ExitHandler> Drop for RegisteredOrderedExitHandler<T> {
+    fn drop(&mut self) {
+        exit_callbacks().remove(self.id);
+    }
+}
+
+/// Handler that is invoked when the application calls `exit_group`, before any of the thread exit
+/// events and the process exit event are invoked. It is only invoked once, on the first thread
+/// that calls `exit_group`. It is not invoked if the process exits in any other way, e.g. through
+/// `dr_exit_process`, a fatal signal, or the last thread calling `exit`.
+#[cfg(target_os = "linux")]
+pub trait PreExitHandler {
+    fn pre_exit(&mut self, context: &mut DrContext<'_>);
+}
+
+#[cfg(target_os = "linux")]
+fn pre_exit_callbacks() -> &'static OrderedCallbacks<PreExitFunc> {
+    PRE_EXIT_CALLBACKS.call_once(|| {
+        OrderedCallbacks::new(Some((attach_pre_exit_events, detach_pre_exit_events)))
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn attach_pre_exit_events() {
+    let priority = PRE_EXIT_PRIORITY.call_once(|| {
+        Priority::new(c"dynamorio-rs pre exit").priority(i32::MIN)
+    });
+    let mut raw_priority = priority.raw();
+
+    unsafe {
+        dr_register_filter_syscall_event(Some(pre_exit_filter_syscall_event));
+        drmgr_register_pre_syscall_event_ex(Some(pre_exit_syscall_event), &mut raw_priority);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn detach_pre_exit_events() {
+    unsafe {
+        dr_unregister_filter_syscall_event(Some(pre_exit_filter_syscall_event));
+        drmgr_unregister_pre_syscall_event(Some(pre_exit_syscall_event));
+    }
+}
+
+#[cfg(target_os = "linux")]
+extern "C" fn pre_exit_filter_syscall_event(
+    _context: *mut core::ffi::c_void,
+    sysnum: i32,
+) -> i8 {
+    (sysnum == SYS_EXIT_GROUP) as i8
+}
+
+#[cfg(target_os = "linux")]
+extern "C" fn pre_exit_syscall_event(
+    context: *mut core::ffi::c_void,
+    sysnum: i32,
+) -> i8 {
+    // Only the first thread to call exit_group invokes the handlers.
+    if sysnum != SYS_EXIT_GROUP || PRE_EXIT_INVOKED.swap(true, Ordering::AcqRel) {
+        return 1;
+    }
+
+    if let Some(callbacks) = PRE_EXIT_CALLBACKS.get() {
+        callbacks.invoke(|func| func(context));
+    }
+
+    1
+}
+
+#[cfg(target_os = "linux")]
+extern "C" fn ordered_pre_exit_event<T: PreExitHandler>(
+    context: *mut core::ffi::c_void,
+    handler: &Mutex<T>,
+) {
+    let mut context = unsafe { DrContext::from_raw(context) };
+
+    if let Ok(mut handler) = handler.lock() {
+        handler.pre_exit(&mut context)
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub struct RegisteredPreExitHandler<T: PreExitHandler> {
+    _handler: Arc<Mutex<T>>,
+    id: usize,
+    _closure: Closure,
+}
+
+#[cfg(target_os = "linux")]
+unsafe impl<T: PreExitHandler> Send for RegisteredPreExitHandler<T> {}
+#[cfg(target_os = "linux")]
+unsafe impl<T: PreExitHandler> Sync for RegisteredPreExitHandler<T> {}
+
+#[cfg(target_os = "linux")]
+impl<T: PreExitHandler> Drop for RegisteredPreExitHandler<T> {
+    fn drop(&mut self) {
+        pre_exit_callbacks().remove(self.id);
+    }
+}
+
+pub trait LowOnMemoryHandler {
+    /// Invoked when DynamoRIO is running low on memory, such that the handler can free caches
+    /// and other memory that it can do without.
+    fn low_on_memory(&mut self);
+}
+
+extern "C" fn low_on_memory_event<T: LowOnMemoryHandler>(
+    handler: &Mutex<T>,
+) {
+    if let Ok(mut handler) = handler.lock() {
+        handler.low_on_memory()
+    }
+}
+
+pub struct RegisteredLowOnMemoryHandler<T: LowOnMemoryHandler> {
+    _handler: Arc<Mutex<T>>,
+    closure: Closure,
+    _priority: Option<Priority>,
+}
+
+unsafe impl<T: LowOnMemoryHandler> Send for RegisteredLowOnMemoryHandler<T> {}
+unsafe impl<T: LowOnMemoryHandler> Sync for RegisteredLowOnMemoryHandler<T> {}
+
+impl<T: LowOnMemoryHandler> Drop for RegisteredLowOnMemoryHandler<T> {
+    fn drop(&mut self) {
+        let func: extern "C" fn() = unsafe {
+            core::mem::transmute(self.closure.code())
+        };
+
+        unsafe {
+            drmgr_unregister_low_on_memory_event(
+                Some(func),
+            );
+        }
+    }
+}
+
+impl Manager {
+    /// Registers a handler for the process exit event. Unlike [`crate::register_exit_handler`],
+    /// the handlers registered through the manager are invoked in the order of their priorities,
+    /// where handlers without a priority use the default priority of zero. Returns `None` if the
+    /// priority conflicts with those of the handlers that are already registered.
+    pub fn register_exit_handler<T: ExitHandler>(
+        &self,
+        handler: &Arc<Mutex<T>>,
+        priority: Option<&Priority>,
+    ) -> Option<RegisteredOrderedExitHandler<T>> {
+        let priority = priority.cloned().unwrap_or_else(|| Priority::new(c""));
+
+        let closure = Closure::new(
+            0,
+            unsafe {
+                core::mem::transmute(ordered_exit_event::<T> as extern "C" fn(_))
+            },
+            Arc::as_ptr(handler) as *mut core::ffi::c_void,
+        );
+
+        let func: ExitFunc = unsafe {
+            core::mem::transmute(closure.code())
+        };
+
+        let id = exit_callbacks().insert(priority, func)?;
+
+        Some(RegisteredOrderedExitHandler {
+            _handler: Arc::clone(handler),
+            id,
+            _closure: closure,
+        })
+    }
+
+    /// Registers a handler that is invoked once when the application calls `exit_group`, i.e.
+    /// before any of the thread exit events and the process exit event are invoked. The handlers
+    /// are invoked on the exiting thread in the order of their priorities, where handlers without
+    /// a priority use the default priority of zero. Returns `None` if the priority conflicts with
+    /// those of the handlers that are already registered. See [`PreExitHandler`] for the cases in
+    /// which the handlers are not invoked.
+    #[cfg(target_os = "linux")]
+    pub fn register_pre_exit_handler<T: PreExitHandler>(
+        &self,
+        handler: &Arc<Mutex<T>>,
+        priority: Option<&Priority>,
+    ) -> Option<RegisteredPreExitHandler<T>> {
+        let priority = priority.cloned().unwrap_or_else(|| Priority::new(c""));
+
+        let closure = Closure::new(
+            1,
+            unsafe {
+                core::mem::transmute(ordered_pre_exit_event::<T> as extern "C" fn(_, _))
+            },
+            Arc::as_ptr(handler) as *mut core::ffi::c_void,
+        );
+
+        let func: PreExitFunc = unsafe {
+            core::mem::transmute(closure.code())
+        };
+
+        let id = pre_exit_callbacks().insert(priority, func)?;
+
+        Some(RegisteredPreExitHandler {
+            _handler: Arc::clone(handler),
+            id,
+            _closure: closure,
+        })
+    }
+
+    pub fn register_low_on_memory_handler<T: LowOnMemoryHandler>(
+        &self,
+        handler: &Arc<Mutex<T>>,
+        priority: Option<&Priority>,
+    ) -> RegisteredLowOnMemoryHandler<T> {
+        let priority = priority.cloned();
+        let mut raw_priority = priority.as_ref().map(Priority::raw);
+
+        let closure = Closure::new(
+            0,
+            unsafe {
+                core::mem::transmute(low_on_memory_event::<T> as extern "C" fn(_))
+            },
+            Arc::as_ptr(handler) as *mut core::ffi::c_void,
+        );
+
+        let func: extern "C" fn() = unsafe {
+            core::mem::transmute(closure.code())
+        };
+
+        unsafe {
+            drmgr_register_low_on_memory_event_ex(
+                Some(func),
+                raw_priority_ptr(&mut raw_priority),
+            );
+        }
+
+        RegisteredLowOnMemoryHandler {
+            _handler: Arc::clone(handler),
+            closure,
+            _priority: priority,
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use crate::{DrContext, MachineContext, Manager, Priority};
+use crate::closure::Closure;
+use crate::manager::priority::raw_priority_ptr;
+use core::marker::PhantomData;
+use drstd::sync::{Arc, Mutex};
+use dynamorio_sys::*;
+
+/// The kind of kernel-mediated control transfer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum KernelXferKind {
+    /// A signal is delivered to the application's signal handler.
+    SignalDelivery,
+    /// The application returns from its signal handler through sigreturn.
+    SignalReturn,
+    /// An asynchronous procedure call is dispatched (Windows).
+    ApcDispatcher,
+    /// An exception is dispatched (Windows).
+    ExceptionDispatcher,
+    /// An exception is raised by the application (Windows).
+    RaiseDispatcher,
+    /// A callback is dispatched (Windows).
+    CallbackDispatcher,
+    /// The application returns from a callback (Windows).
+    CallbackReturn,
+    /// The application resumes execution through NtContinue (Windows).
+    Continue,
+    /// The application changes the context of a thread through NtSetContextThread (Windows).
+    SetContextThread,
+    /// A client redirected execution, e.g. through `dr_redirect_execution`.
+    ClientRedirect,
+    /// A restartable sequence was aborted (Linux).
+    RseqAbort,
+    /// A kind of transfer that is not known to this crate.
+    Unknown(u32),
+}
+
+impl From<dr_kernel_xfer_type_t> for KernelXferKind {
+    fn from(kind: dr_kernel_xfer_type_t) -> Self {
+        match kind {
+            dr_kernel_xfer_type_t::DR_XFER_SIGNAL_DELIVERY => Self::SignalDelivery,
+            dr_kernel_xfer_type_t::DR_XFER_SIGNAL_RETURN => Self::SignalReturn,
+            dr_kernel_xfer_type_t::DR_XFER_APC_DISPATCHER => Self::ApcDispatcher,
+            dr_kernel_xfer_type_t::DR_XFER_EXCEPTION_DISPATCHER => Self::ExceptionDispatcher,
+            dr_kernel_xfer_type_t::DR_XFER_RAISE_DISPATCHER => Self::RaiseDispatcher,
+            dr_kernel_xfer_type_t::DR_XFER_CALLBACK_DISPATCHER => Self::CallbackDispatcher,
+            dr_kernel_xfer_type_t::DR_XFER_CALLBACK_RETURN => Self::CallbackReturn,
+            dr_kernel_xfer_type_t::DR_XFER_CONTINUE => Self::Continue,
+            dr_kernel_xfer_type_t::DR_XFER_SET_CONTEXT_THREAD => Self::SetContextThread,
+            dr_kernel_xfer_type_t::DR_XFER_CLIENT_REDIRECT => Self::ClientRedirect,
+            dr_kernel_xfer_type_t::DR_XFER_RSEQ_ABORT => Self::RseqAbort,
+            kind => Self::Unknown(kind.0),
+        }
+    }
+}
+
+/// Describes a kernel-mediated control transfer, such as the delivery of a signal.
+pub struct KernelXferInfo<'a> {
+    raw: *const dr_kernel_xfer_info_t,
+    _marker: PhantomData<&'a dr_kernel_xfer_info_t>,
+}
+
+impl<'a> KernelXferInfo<'a> {
+    /// # Safety
+    /// The caller must ensure that `raw` points to a valid kernel transfer info that outlives
+    /// `'a`.
+    pub unsafe fn from_raw(raw: *const dr_kernel_xfer_info_t) -> Self {
+        Self {
+            raw,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn raw(&self) -> *const dr_kernel_xfer_info_t {
+        self.raw
+    }
+
+    pub fn kind(&self) -> KernelXferKind {
+        unsafe {
+            KernelXferKind::from((*self.raw).type_)
+        }
+    }
+
+    /// Returns the machine context of the application at the source of the transfer, if it is
+    /// available.
+    pub fn source_machine_context(&self) -> Option<&MachineContext> {
+        unsafe {
+            if (*self.raw).source_mcontext.is_null() {
+                return None;
+            }
+
+            Some(&*((*self.raw).source_mcontext as *const MachineContext))
+        }
+    }
+
+    /// Returns the pc at the source of the transfer, if it is available.
+    #[cfg(target_arch = "x86_64")]
+    pub fn source_pc(&self) -> Option<usize> {
+        self.source_machine_context()
+            .map(MachineContext::pc)
+    }
+
+    /// Returns the stack pointer at the source of the transfer, if it is available.
+    #[cfg(target_arch = "x86_64")]
+    pub fn source_sp(&self) -> Option<usize> {
+        self.source_machine_context()
+            .map(MachineContext::sp)
+    }
+
+    /// Returns the pc at which the application resumes execution.
+    pub fn target_pc(&self) -> usize {
+        unsafe {
+            (*self.raw).target_pc as usize
+        }
+    }
+
+    /// Returns the stack pointer with which the application resumes execution.
+    pub fn target_sp(&self) -> usize {
+        unsafe {
+            (*self.raw).target_xsp as usize
+        }
+    }
+
+    /// Returns the signal number for [`KernelXferKind::SignalDelivery`] and
+    /// [`KernelXferKind::SignalReturn`].
+    pub fn signal(&self) -> Option<i32> {
+        match self.kind() {
+            KernelXferKind::SignalDelivery | KernelXferKind::SignalReturn => unsafe {
+                Some((*self.raw).sig)
+            },
+            _ => None,
+        }
+    }
+}
+
+pub trait KernelXferHandler {
+    fn kernel_xfer(&mut self, context: &mut DrContext<'_>, info: &KernelXferInfo<'_>);
+}
+
+pub struct RegisteredKernelXferHandler<T: KernelXferHandler> {
+    _handler: Arc<Mutex<T>>,
+    closure: Closure,
+    _priority: Option<Priority>,
+}
+
+unsafe impl<T: KernelXferHandler> Send for RegisteredKernelXferHandler<T> {}
+unsafe impl<T: KernelXferHandler> Sync for RegisteredKernelXferHandler<T> {}
+
+impl<T: KernelXferHandler> Drop for RegisteredKernelXferHandler<T> {
+    fn drop(&mut self) {
+        let func: extern "C" fn(*mut core::ffi::c_void, *const dr_kernel_xfer_info_t) = unsafe {
+            core::mem::transmute(self.closure.code())
+        };
+
+        unsafe {
+            drmgr_unregister_kernel_xfer_event(
+                Some(func),
+            );
+        }
+    }
+}
+
+extern "C" fn kernel_xfer_event<T: KernelXferHandler>(
+    context: *mut core::ffi::c_void,
+    info: *const dr_kernel_xfer_info_t,
+    handler: &Mutex<T>,
+) {
+    let mut context = unsafe { DrContext::from_raw(context) };
+    let info = unsafe { KernelXferInfo::from_raw(info) };
+
+    if let Ok(mut handler) = handler.lock() {
+        handler.kernel_xfer(&mut context, &info);
+    }
+}
+
+impl Manager {
+    pub fn register_kernel_xfer_handler<T: KernelXferHandler>(
+        &self,
+        handler: &Arc<Mutex<T>>,
+        priority: Option<&Priority>,
+    ) -> RegisteredKernelXferHandler<T> {
+        let priority = priority.cloned();
+        let mut raw_priority = priority.as_ref().map(Priority::raw);
+
+        let closure = Closure::new(
+            2,
+            unsafe {
+                core::mem::transmute(kernel_xfer_event::<T> as extern "C" fn(_, _, _))
+            },
+            Arc::as_ptr(handler) as *mut core::ffi::c_void,
+        );
+
+        let func: extern "C" fn(*mut core::ffi::c_void, *const dr_kernel_xfer_info_t) = unsafe {
+            core::mem::transmute(closure.code())
+        };
+
+        unsafe {
+            drmgr_register_kernel_xfer_event_ex(
+                Some(func),
+                raw_priority_ptr(&mut raw_priority),
+            );
+        }
+
+        RegisteredKernelXferHandler {
+            _handler: Arc::clone(handler),
+            closure,
+            _priority: priority,
+        }
+    }
+}
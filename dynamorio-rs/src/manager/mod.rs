@@ -1,14 +1,30 @@
 pub mod basic_block;
+pub mod exit;
+pub mod kernel_xfer;
 pub mod module;
+pub mod opcode;
+pub mod priority;
+pub mod restore_state;
+#[cfg(target_os = "linux")]
+pub mod signal;
 pub mod syscall;
 pub mod thread;
+pub mod tls;
 
 use dynamorio_sys::*;
 
 pub use basic_block::*;
+pub use exit::*;
+pub use kernel_xfer::*;
 pub use module::*;
+pub use opcode::*;
+pub use priority::Priority;
+pub use restore_state::*;
+#[cfg(target_os = "linux")]
+pub use signal::*;
 pub use syscall::*;
 pub use thread::*;
+pub use tls::TlsKey;
 
 pub use dynamorio_sys::dr_emit_flags_t;
 
@@ -1,27 +1,43 @@
-use crate::{Context, Manager, ModuleData};
+use crate::{DrContext, Manager, Priority, ModuleData};
+use crate::closure::Closure;
+use crate::manager::priority::raw_priority_ptr;
 use drstd::sync::{Arc, Mutex};
 use dynamorio_sys::*;
 
 pub trait ModuleHandler {
-    fn load_module(&mut self, context: &mut Context, module: &mut ModuleData, loaded: bool);
-    fn unload_module(&mut self, context: &mut Context, module: &mut ModuleData);
+    fn load_module(&mut self, context: &mut DrContext<'_>, module: &mut ModuleData, loaded: bool);
+    fn unload_module(&mut self, context: &mut DrContext<'_>, module: &mut ModuleData);
 }
 
 pub struct RegisteredModuleHandler<T: ModuleHandler> {
     _handler: Arc<Mutex<T>>,
+    module_load_closure: Closure,
+    module_unload_closure: Closure,
+    _priority: Option<Priority>,
 }
 
+unsafe impl<T: ModuleHandler> Send for RegisteredModuleHandler<T> {}
+unsafe impl<T: ModuleHandler> Sync for RegisteredModuleHandler<T> {}
+
 impl<T: ModuleHandler> Drop for RegisteredModuleHandler<T> {
     fn drop(&mut self) {
+        let module_load_func: extern "C" fn(*mut core::ffi::c_void, *const module_data_t, i8) = unsafe {
+            core::mem::transmute(self.module_load_closure.code())
+        };
+
+        let module_unload_func: extern "C" fn(*mut core::ffi::c_void, *const module_data_t) = unsafe {
+            core::mem::transmute(self.module_unload_closure.code())
+        };
+
         unsafe {
-            drmgr_unregister_module_load_event_user_data(
-                Some(module_load_event::<T>),
+            drmgr_unregister_module_load_event(
+                Some(module_load_func),
             );
         }
 
         unsafe {
-            drmgr_unregister_module_unload_event_user_data(
-                Some(module_unload_event::<T>),
+            drmgr_unregister_module_unload_event(
+                Some(module_unload_func),
             );
         }
     }
@@ -31,35 +47,31 @@ extern "C" fn module_load_event<T: ModuleHandler>(
     context: *mut core::ffi::c_void,
     module: *const module_data_t,
     loaded: i8,
-    user_data: *mut core::ffi::c_void,
+    handler: &Mutex<T>,
 ) {
     let mut module = ModuleData::from_raw(module as _);
     let loaded = loaded != 0;
-    let mut context = Context::from_raw(context);
-    let handler = unsafe { &*(user_data as *mut Mutex<T>) };
+    let mut context = unsafe { DrContext::from_raw(context) };
 
     if let Ok(mut handler) = handler.lock() {
         handler.load_module(&mut context, &mut module, loaded);
     }
 
-    core::mem::forget(context);
     core::mem::forget(module);
 }
 
 extern "C" fn module_unload_event<T: ModuleHandler>(
     context: *mut core::ffi::c_void,
     module: *const module_data_t,
-    user_data: *mut core::ffi::c_void,
+    handler: &Mutex<T>,
 ) {
     let mut module = ModuleData::from_raw(module as _);
-    let mut context = Context::from_raw(context);
-    let handler = unsafe { &*(user_data as *mut Mutex<T>) };
+    let mut context = unsafe { DrContext::from_raw(context) };
 
     if let Ok(mut handler) = handler.lock() {
         handler.unload_module(&mut context, &mut module);
     }
 
-    core::mem::forget(context);
     core::mem::forget(module);
 }
 
@@ -67,25 +79,54 @@ impl Manager {
     pub fn register_module_handler<T: ModuleHandler>(
         &self,
         handler: &Arc<Mutex<T>>,
+        priority: Option<&Priority>,
     ) -> RegisteredModuleHandler<T> {
+        let priority = priority.cloned();
+        let mut raw_priority = priority.as_ref().map(Priority::raw);
+
+        let module_load_closure = Closure::new(
+            3,
+            unsafe {
+                core::mem::transmute(module_load_event::<T> as extern "C" fn(_, _, _, _))
+            },
+            Arc::as_ptr(handler) as *mut core::ffi::c_void,
+        );
+
+        let module_load_func: extern "C" fn(*mut core::ffi::c_void, *const module_data_t, i8) = unsafe {
+            core::mem::transmute(module_load_closure.code())
+        };
+
+        let module_unload_closure = Closure::new(
+            2,
+            unsafe {
+                core::mem::transmute(module_unload_event::<T> as extern "C" fn(_, _, _))
+            },
+            Arc::as_ptr(handler) as *mut core::ffi::c_void,
+        );
+
+        let module_unload_func: extern "C" fn(*mut core::ffi::c_void, *const module_data_t) = unsafe {
+            core::mem::transmute(module_unload_closure.code())
+        };
+
         unsafe {
-            drmgr_register_module_load_event_user_data(
-                Some(module_load_event::<T>),
-                core::ptr::null_mut(),
-                Arc::as_ptr(&handler) as *mut core::ffi::c_void,
+            drmgr_register_module_load_event_ex(
+                Some(module_load_func),
+                raw_priority_ptr(&mut raw_priority),
             );
         }
 
         unsafe {
-            drmgr_register_module_unload_event_user_data(
-                Some(module_unload_event::<T>),
-                core::ptr::null_mut(),
-                Arc::as_ptr(&handler) as *mut core::ffi::c_void,
+            drmgr_register_module_unload_event_ex(
+                Some(module_unload_func),
+                raw_priority_ptr(&mut raw_priority),
             );
         }
 
         RegisteredModuleHandler {
-            _handler: Arc::clone(&handler),
+            _handler: Arc::clone(handler),
+            module_load_closure,
+            module_unload_closure,
+            _priority: priority,
         }
     }
 }
@@ -0,0 +1,124 @@
+use crate::{BlockTag, DrContext, InstrListRef, InstrRef, Manager, Opcode, Priority};
+use crate::closure::Closure;
+use crate::manager::priority::raw_priority_ptr;
+use dynamorio_sys::*;
+use drstd::sync::{Arc, Mutex};
+
+type OpcodeInsertionFunc = extern "C" fn(*mut core::ffi::c_void, *mut core::ffi::c_void, *mut instrlist_t, *mut instr_t, i8, i8, *mut core::ffi::c_void) -> dr_emit_flags_t;
+
+/// Handler for the instrumentation of instructions with a specific opcode. Unlike
+/// [`BasicBlockHandler::instrument`], the handler is only invoked for the instructions that match
+/// the opcode it was registered for.
+///
+/// [`BasicBlockHandler::instrument`]: crate::BasicBlockHandler::instrument
+pub trait OpcodeHandler {
+    fn instrument(
+        &mut self,
+        context: &mut DrContext<'_>,
+        tag: BlockTag,
+        bb: &mut InstrListRef<'_>,
+        instruction: InstrRef<'_>,
+        for_trace: bool,
+        translating: bool,
+    ) -> dr_emit_flags_t;
+}
+
+pub struct RegisteredOpcodeHandler<T: OpcodeHandler> {
+    _handler: Arc<Mutex<T>>,
+    opcode: Opcode,
+    closure: Closure,
+    _priority: Option<Priority>,
+}
+
+unsafe impl<T: OpcodeHandler> Send for RegisteredOpcodeHandler<T> {}
+unsafe impl<T: OpcodeHandler> Sync for RegisteredOpcodeHandler<T> {}
+
+impl<T: OpcodeHandler> RegisteredOpcodeHandler<T> {
+    /// Returns the opcode that the handler is registered for.
+    pub fn opcode(&self) -> Opcode {
+        self.opcode
+    }
+}
+
+impl<T: OpcodeHandler> Drop for RegisteredOpcodeHandler<T> {
+    fn drop(&mut self) {
+        let func: OpcodeInsertionFunc = unsafe {
+            core::mem::transmute(self.closure.code())
+        };
+
+        unsafe {
+            drmgr_unregister_opcode_instrumentation_event(
+                Some(func),
+                self.opcode.raw() as i32,
+            );
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+extern "C" fn opcode_instrumentation_event<T: OpcodeHandler>(
+    context: *mut core::ffi::c_void,
+    tag: *mut core::ffi::c_void,
+    bb: *mut instrlist_t,
+    instr: *mut instr_t,
+    for_trace: i8,
+    translating: i8,
+    _user_data: *mut core::ffi::c_void,
+    handler: &Mutex<T>,
+) -> dr_emit_flags_t {
+    let mut bb = unsafe { InstrListRef::from_raw(context, bb) };
+    let instr = unsafe { InstrRef::from_raw(instr) };
+    let for_trace = for_trace != 0;
+    let translating = translating != 0;
+    let tag = BlockTag::from_raw(tag);
+    let mut context = unsafe { DrContext::from_raw(context) };
+    let mut flags = dr_emit_flags_t::DR_EMIT_DEFAULT;
+
+    if let Ok(mut handler) = handler.lock() {
+        flags = handler.instrument(&mut context, tag, &mut bb, instr, for_trace, translating);
+    }
+
+    flags
+}
+
+impl Manager {
+    /// Registers a handler that is only invoked for instructions with the given opcode during
+    /// the instrumentation phase.
+    pub fn instrument_opcode<T: OpcodeHandler>(
+        &self,
+        opcode: Opcode,
+        handler: &Arc<Mutex<T>>,
+        priority: Option<&Priority>,
+    ) -> RegisteredOpcodeHandler<T> {
+        let priority = priority.cloned();
+        let mut raw_priority = priority.as_ref().map(Priority::raw);
+
+        let closure = Closure::new(
+            7,
+            unsafe {
+                core::mem::transmute(opcode_instrumentation_event::<T> as extern "C" fn(_, _, _, _, _, _, _, _) -> _)
+            },
+            Arc::as_ptr(handler) as *mut core::ffi::c_void,
+        );
+
+        let func: OpcodeInsertionFunc = unsafe {
+            core::mem::transmute(closure.code())
+        };
+
+        unsafe {
+            drmgr_register_opcode_instrumentation_event(
+                Some(func),
+                opcode.raw() as i32,
+                raw_priority_ptr(&mut raw_priority),
+                core::ptr::null_mut(),
+            );
+        }
+
+        RegisteredOpcodeHandler {
+            _handler: Arc::clone(handler),
+            opcode,
+            closure,
+            _priority: priority,
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use alloc::borrow::ToOwned;
+use alloc::ffi::CString;
+use alloc::vec::Vec;
+use core::ffi::CStr;
+use dynamorio_sys::*;
+
+/// Describes where a drmgr event callback should be ordered relative to the callbacks registered
+/// by other components. Callbacks are ordered by their numeric priority, where lower values are
+/// invoked first, subject to the constraint that a callback is invoked before or after the
+/// callbacks with the given names.
+///
+/// The registration functions of [`crate::Manager`] take an optional priority, where `None` uses
+/// the default ordering of drmgr.
+#[derive(Clone, Debug)]
+pub struct Priority {
+    name: CString,
+    before: Option<CString>,
+    after: Option<CString>,
+    priority: i32,
+}
+
+impl Priority {
+    /// Creates a new priority with the given name and the default numeric priority of zero.
+    pub fn new(name: &CStr) -> Self {
+        Self {
+            name: name.to_owned(),
+            before: None,
+            after: None,
+            priority: 0,
+        }
+    }
+
+    /// Orders the callback before the callback with the given name.
+    pub fn before(mut self, name: &CStr) -> Self {
+        self.before = Some(name.to_owned());
+        self
+    }
+
+    /// Orders the callback after the callback with the given name.
+    pub fn after(mut self, name: &CStr) -> Self {
+        self.after = Some(name.to_owned());
+        self
+    }
+
+    /// Sets the numeric priority, where lower values are invoked first.
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn name(&self) -> &CStr {
+        &self.name
+    }
+
+    /// Returns the raw priority. The strings it points to are owned by `self`, so `self` must be
+    /// kept alive for as long as the callback is registered.
+    pub(crate) fn raw(&self) -> drmgr_priority_t {
+        drmgr_priority_t {
+            struct_size: core::mem::size_of::<drmgr_priority_t>(),
+            name: self.name.as_ptr(),
+            before: self.before
+                .as_ref()
+                .map(|before| before.as_ptr())
+                .unwrap_or(core::ptr::null()),
+            after: self.after
+                .as_ref()
+                .map(|after| after.as_ptr())
+                .unwrap_or(core::ptr::null()),
+            priority: self.priority,
+        }
+    }
+}
+
+/// Returns a pointer to the raw priority, or a null pointer if there is no priority, such that
+/// drmgr uses the default priority instead.
+pub(crate) fn raw_priority_ptr(priority: &mut Option<drmgr_priority_t>) -> *mut drmgr_priority_t {
+    priority
+        .as_mut()
+        .map_or(core::ptr::null_mut(), |priority| priority as *mut _)
+}
+
+/// Returns the index at which a callback with the given priority should be inserted into a list
+/// of callbacks that is already ordered, following the same rules as drmgr: the callback is
+/// placed after the callback named by `after` and any callbacks that must run before it, before
+/// the callback named by `before` and any callbacks that must run after it, and otherwise by
+/// numeric priority, where callbacks with equal priorities are kept in registration order.
+/// Returns `None` if these constraints conflict.
+///
+/// This is used for the process exit event and the pre-exit handlers, which drmgr cannot order
+/// for us: drmgr does not provide an exit event, and all pre-exit handlers are invoked from a
+/// single pre-syscall callback.
+pub(crate) fn insertion_index<'a, I>(list: I, new: &Priority) -> Option<usize>
+where
+    I: IntoIterator<Item = &'a Priority>,
+{
+    let list: Vec<&Priority> = list.into_iter().collect();
+
+    // The lowest index is right after the last callback that must run before the new one.
+    let lo = list
+        .iter()
+        .rposition(|existing| {
+            new.after.as_ref() == Some(&existing.name) ||
+                existing.before.as_ref() == Some(&new.name)
+        })
+        .map_or(0, |index| index + 1);
+
+    // The highest index is at the first callback that must run after the new one.
+    let hi = list
+        .iter()
+        .position(|existing| {
+            new.before.as_ref() == Some(&existing.name) ||
+                existing.after.as_ref() == Some(&new.name)
+        })
+        .unwrap_or(list.len());
+
+    if lo > hi {
+        return None;
+    }
+
+    let index = list[lo..hi]
+        .iter()
+        .position(|existing| new.priority < existing.priority)
+        .map_or(hi, |index| lo + index);
+
+    Some(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(list: &[Priority], new: &Priority) -> Option<usize> {
+        insertion_index(list, new)
+    }
+
+    #[test]
+    fn orders_by_priority() {
+        let list = [
+            Priority::new(c"a").priority(-10),
+            Priority::new(c"b").priority(10),
+        ];
+
+        assert_eq!(index(&list, &Priority::new(c"c").priority(-20)), Some(0));
+        assert_eq!(index(&list, &Priority::new(c"c")), Some(1));
+        assert_eq!(index(&list, &Priority::new(c"c").priority(20)), Some(2));
+    }
+
+    #[test]
+    fn keeps_registration_order_for_ties() {
+        let list = [
+            Priority::new(c"a"),
+            Priority::new(c"b"),
+        ];
+
+        assert_eq!(index(&list, &Priority::new(c"c")), Some(2));
+    }
+
+    #[test]
+    fn honours_before() {
+        let list = [
+            Priority::new(c"a").priority(-10),
+            Priority::new(c"b").priority(10),
+        ];
+
+        assert_eq!(index(&list, &Priority::new(c"c").priority(20).before(c"a")), Some(0));
+    }
+
+    #[test]
+    fn honours_after() {
+        let list = [
+            Priority::new(c"a").priority(-10),
+            Priority::new(c"b").priority(10),
+        ];
+
+        assert_eq!(index(&list, &Priority::new(c"c").priority(-20).after(c"b")), Some(2));
+    }
+
+    #[test]
+    fn honours_existing_before() {
+        let list = [
+            Priority::new(c"x").priority(10),
+            Priority::new(c"y").priority(20).before(c"n"),
+        ];
+
+        assert_eq!(index(&list, &Priority::new(c"n").priority(5)), Some(2));
+    }
+
+    #[test]
+    fn honours_existing_after() {
+        let list = [
+            Priority::new(c"x").priority(-10).after(c"n"),
+            Priority::new(c"y").priority(10),
+        ];
+
+        assert_eq!(index(&list, &Priority::new(c"n").priority(20)), Some(0));
+    }
+
+    #[test]
+    fn rejects_conflicting_constraints() {
+        let list = [
+            Priority::new(c"a"),
+            Priority::new(c"b"),
+        ];
+
+        assert_eq!(index(&list, &Priority::new(c"c").after(c"b").before(c"a")), None);
+    }
+}
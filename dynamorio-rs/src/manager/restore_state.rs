@@ -0,0 +1,163 @@
+use crate::{DrContext, FaultFragmentInfo, MachineContext, Manager, Priority};
+use crate::closure::Closure;
+use crate::manager::priority::raw_priority_ptr;
+use core::marker::PhantomData;
+use drstd::sync::{Arc, Mutex};
+use dynamorio_sys::*;
+
+/// Describes the state that DynamoRIO is translating from the code cache back to the application,
+/// e.g. because a fault occurred inside instrumentation.
+pub struct RestoreStateInfo<'a> {
+    context: *mut core::ffi::c_void,
+    raw: *mut dr_restore_state_info_t,
+    _marker: PhantomData<&'a mut dr_restore_state_info_t>,
+}
+
+impl<'a> RestoreStateInfo<'a> {
+    /// # Safety
+    /// The caller must ensure that `context` is the drcontext of the current thread and that `raw`
+    /// points to a valid restore state info that outlives `'a`.
+    pub unsafe fn from_raw(context: *mut core::ffi::c_void, raw: *mut dr_restore_state_info_t) -> Self {
+        Self {
+            context,
+            raw,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn raw(&self) -> *mut dr_restore_state_info_t {
+        self.raw
+    }
+
+    /// Returns the machine context as translated to the application so far.
+    pub fn machine_context(&self) -> &MachineContext {
+        unsafe {
+            &*((*self.raw).mcontext as *const MachineContext)
+        }
+    }
+
+    /// Returns the machine context as translated to the application so far, which the handler
+    /// should fix up to undo the effects of its instrumentation, e.g. by restoring registers that
+    /// were stolen.
+    pub fn machine_context_mut(&mut self) -> &mut MachineContext {
+        unsafe {
+            &mut *((*self.raw).mcontext as *mut MachineContext)
+        }
+    }
+
+    /// Returns the untranslated machine context in the code cache, if it is available.
+    pub fn raw_machine_context(&self) -> Option<&MachineContext> {
+        unsafe {
+            if (*self.raw).raw_mcontext_valid == 0 {
+                return None;
+            }
+
+            Some(&*(&(*self.raw).raw_mcontext as *const dr_mcontext_t as *const MachineContext))
+        }
+    }
+
+    /// Returns the code cache pc at which the fault occurred, if the untranslated machine context
+    /// is available.
+    #[cfg(target_arch = "x86_64")]
+    pub fn fault_pc(&self) -> Option<usize> {
+        self.raw_machine_context()
+            .map(MachineContext::pc)
+    }
+
+    /// Returns information about the code fragment that is being translated.
+    pub fn fragment_info(&self) -> FaultFragmentInfo<'_> {
+        unsafe {
+            FaultFragmentInfo::from_raw(self.context, &(*self.raw).fragment_info)
+        }
+    }
+}
+
+pub trait RestoreStateHandler {
+    /// Invoked when DynamoRIO translates the machine context of a thread from the code cache back
+    /// to the application. If `restore_memory` is set, the handler should also restore any
+    /// application memory that its instrumentation modified. Returns whether the translation
+    /// succeeded.
+    fn restore_state(
+        &mut self,
+        context: &mut DrContext<'_>,
+        restore_memory: bool,
+        info: &mut RestoreStateInfo<'_>,
+    ) -> bool;
+}
+
+pub struct RegisteredRestoreStateHandler<T: RestoreStateHandler> {
+    _handler: Arc<Mutex<T>>,
+    closure: Closure,
+    _priority: Option<Priority>,
+}
+
+unsafe impl<T: RestoreStateHandler> Send for RegisteredRestoreStateHandler<T> {}
+unsafe impl<T: RestoreStateHandler> Sync for RegisteredRestoreStateHandler<T> {}
+
+impl<T: RestoreStateHandler> Drop for RegisteredRestoreStateHandler<T> {
+    fn drop(&mut self) {
+        let func: extern "C" fn(*mut core::ffi::c_void, i8, *mut dr_restore_state_info_t) -> i8 = unsafe {
+            core::mem::transmute(self.closure.code())
+        };
+
+        unsafe {
+            drmgr_unregister_restore_state_ex_event(
+                Some(func),
+            );
+        }
+    }
+}
+
+extern "C" fn restore_state_event<T: RestoreStateHandler>(
+    context: *mut core::ffi::c_void,
+    restore_memory: i8,
+    info: *mut dr_restore_state_info_t,
+    handler: &Mutex<T>,
+) -> i8 {
+    let mut info = unsafe { RestoreStateInfo::from_raw(context, info) };
+    let restore_memory = restore_memory != 0;
+    let mut context = unsafe { DrContext::from_raw(context) };
+    let mut result = true;
+
+    if let Ok(mut handler) = handler.lock() {
+        result = handler.restore_state(&mut context, restore_memory, &mut info);
+    }
+
+    result as i8
+}
+
+impl Manager {
+    pub fn register_restore_state_handler<T: RestoreStateHandler>(
+        &self,
+        handler: &Arc<Mutex<T>>,
+        priority: Option<&Priority>,
+    ) -> RegisteredRestoreStateHandler<T> {
+        let priority = priority.cloned();
+        let mut raw_priority = priority.as_ref().map(Priority::raw);
+
+        let closure = Closure::new(
+            3,
+            unsafe {
+                core::mem::transmute(restore_state_event::<T> as extern "C" fn(_, _, _, _) -> _)
+            },
+            Arc::as_ptr(handler) as *mut core::ffi::c_void,
+        );
+
+        let func: extern "C" fn(*mut core::ffi::c_void, i8, *mut dr_restore_state_info_t) -> i8 = unsafe {
+            core::mem::transmute(closure.code())
+        };
+
+        unsafe {
+            drmgr_register_restore_state_ex_event_ex(
+                Some(func),
+                raw_priority_ptr(&mut raw_priority),
+            );
+        }
+
+        RegisteredRestoreStateHandler {
+            _handler: Arc::clone(handler),
+            closure,
+            _priority: priority,
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use crate::{DrContext, FaultFragmentInfo, MachineContext, Manager, Priority};
+use crate::closure::Closure;
+use crate::manager::priority::raw_priority_ptr;
+use core::marker::PhantomData;
+use drstd::sync::{Arc, Mutex};
+use dynamorio_sys::*;
+
+const SIGBUS: i32 = 7;
+const SIGSEGV: i32 = 11;
+
+/// Describes a signal that is about to be delivered to the application.
+pub struct SignalInfo<'a> {
+    raw: *mut dr_siginfo_t,
+    _marker: PhantomData<&'a mut dr_siginfo_t>,
+}
+
+impl<'a> SignalInfo<'a> {
+    /// # Safety
+    /// The caller must ensure that `raw` points to a valid signal info that outlives `'a`.
+    pub unsafe fn from_raw(raw: *mut dr_siginfo_t) -> Self {
+        Self {
+            raw,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn raw(&self) -> *mut dr_siginfo_t {
+        self.raw
+    }
+
+    /// Returns the signal number.
+    pub fn signal(&self) -> i32 {
+        unsafe {
+            (*self.raw).sig
+        }
+    }
+
+    /// Returns the address that was being accessed for SIGSEGV and SIGBUS, or `None` for any other
+    /// signal, as DynamoRIO only sets it for those.
+    pub fn access_address(&self) -> Option<usize> {
+        if !matches!(self.signal(), SIGSEGV | SIGBUS) {
+            return None;
+        }
+
+        unsafe {
+            Some((*self.raw).access_address as usize)
+        }
+    }
+
+    /// Returns whether the application has the signal blocked, in which case the signal is
+    /// delivered once the application unblocks it.
+    pub fn is_blocked(&self) -> bool {
+        unsafe {
+            (*self.raw).blocked != 0
+        }
+    }
+
+    /// Returns the application machine context at the time of the signal, translated to the
+    /// application code.
+    pub fn machine_context(&self) -> &MachineContext {
+        unsafe {
+            &*((*self.raw).mcontext as *const MachineContext)
+        }
+    }
+
+    /// Returns the application machine context at the time of the signal. Any changes are visible
+    /// to the application's signal handler.
+    pub fn machine_context_mut(&mut self) -> &mut MachineContext {
+        unsafe {
+            &mut *((*self.raw).mcontext as *mut MachineContext)
+        }
+    }
+
+    /// Returns the untranslated machine context at the time of the signal, which may point into
+    /// the code cache, if it is available.
+    pub fn raw_machine_context(&self) -> Option<&MachineContext> {
+        unsafe {
+            if (*self.raw).raw_mcontext_valid == 0 || (*self.raw).raw_mcontext.is_null() {
+                return None;
+            }
+
+            Some(&*((*self.raw).raw_mcontext as *const MachineContext))
+        }
+    }
+
+    /// Returns information about the code fragment in which the signal occurred.
+    pub fn fault_fragment_info(&self) -> FaultFragmentInfo<'_> {
+        unsafe {
+            FaultFragmentInfo::from_raw((*self.raw).drcontext, &(*self.raw).fault_fragment_info)
+        }
+    }
+}
+
+/// The action to take for a signal.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SignalAction {
+    /// Deliver the signal to the application as normal.
+    Deliver,
+    /// Suppress the signal, as if it never happened.
+    Suppress,
+    /// Deliver the signal to the application, but bypass the signal handler of the application
+    /// and use the default action instead.
+    Bypass,
+    /// Suppress the signal and resume execution with the machine context as modified through
+    /// [`SignalInfo::machine_context_mut`].
+    Redirect,
+}
+
+pub trait SignalHandler {
+    fn handle_signal(&mut self, context: &mut DrContext<'_>, info: &mut SignalInfo<'_>) -> SignalAction;
+}
+
+pub struct RegisteredSignalHandler<T: SignalHandler> {
+    _handler: Arc<Mutex<T>>,
+    closure: Closure,
+    _priority: Option<Priority>,
+}
+
+unsafe impl<T: SignalHandler> Send for RegisteredSignalHandler<T> {}
+unsafe impl<T: SignalHandler> Sync for RegisteredSignalHandler<T> {}
+
+impl<T: SignalHandler> Drop for RegisteredSignalHandler<T> {
+    fn drop(&mut self) {
+        let func: extern "C" fn(*mut core::ffi::c_void, *mut dr_siginfo_t) -> dr_signal_action_t = unsafe {
+            core::mem::transmute(self.closure.code())
+        };
+
+        unsafe {
+            drmgr_unregister_signal_event(
+                Some(func),
+            );
+        }
+    }
+}
+
+extern "C" fn signal_event<T: SignalHandler>(
+    context: *mut core::ffi::c_void,
+    info: *mut dr_siginfo_t,
+    handler: &Mutex<T>,
+) -> dr_signal_action_t {
+    let mut context = unsafe { DrContext::from_raw(context) };
+    let mut info = unsafe { SignalInfo::from_raw(info) };
+    let mut action = SignalAction::Deliver;
+
+    if let Ok(mut handler) = handler.lock() {
+        action = handler.handle_signal(&mut context, &mut info);
+    }
+
+    match action {
+        SignalAction::Deliver => dr_signal_action_t::DR_SIGNAL_DELIVER,
+        SignalAction::Suppress => dr_signal_action_t::DR_SIGNAL_SUPPRESS,
+        SignalAction::Bypass => dr_signal_action_t::DR_SIGNAL_BYPASS,
+        SignalAction::Redirect => dr_signal_action_t::DR_SIGNAL_REDIRECT,
+    }
+}
+
+impl Manager {
+    pub fn register_signal_handler<T: SignalHandler>(
+        &self,
+        handler: &Arc<Mutex<T>>,
+        priority: Option<&Priority>,
+    ) -> RegisteredSignalHandler<T> {
+        let priority = priority.cloned();
+        let mut raw_priority = priority.as_ref().map(Priority::raw);
+
+        let closure = Closure::new(
+            2,
+            unsafe {
+                core::mem::transmute(signal_event::<T> as extern "C" fn(_, _, _) -> _)
+            },
+            Arc::as_ptr(handler) as *mut core::ffi::c_void,
+        );
+
+        let func: extern "C" fn(*mut core::ffi::c_void, *mut dr_siginfo_t) -> dr_signal_action_t = unsafe {
+            core::mem::transmute(closure.code())
+        };
+
+        unsafe {
+            drmgr_register_signal_event_ex(
+                Some(func),
+                raw_priority_ptr(&mut raw_priority),
+            );
+        }
+
+        RegisteredSignalHandler {
+            _handler: Arc::clone(handler),
+            closure,
+            _priority: priority,
+        }
+    }
+}
@@ -1,85 +1,169 @@
-use crate::Manager;
-use crate::{AfterSyscallContext, BeforeSyscallContext, Context};
+use crate::{Manager, Priority};
+use crate::{AfterSyscallContext, BeforeSyscallContext, DrContext};
 use crate::closure::Closure;
+use crate::manager::priority::raw_priority_ptr;
+use crate::manager::tls::TlsKey;
+use alloc::boxed::Box;
 use drstd::sync::{Arc, Mutex};
 use dynamorio_sys::*;
 
 pub trait SyscallHandler {
-    fn filter_syscall(&mut self, context: &mut Context, sysno: i32) -> bool;
-    fn before_syscall(&mut self, context: &mut BeforeSyscallContext, sysno: i32) -> bool;
-    fn after_syscall(&mut self, context: &mut AfterSyscallContext, sysno: i32);
+    /// Per-thread state that is returned by [`SyscallHandler::before_syscall`] and passed to
+    /// [`SyscallHandler::after_syscall`] for the same system call on the same thread. The state
+    /// is stored in a drmgr TLS field by the crate.
+    type State;
+
+    fn filter_syscall(&mut self, context: &mut DrContext<'_>, sysno: i32) -> bool;
+
+    /// Invoked before the system call is executed. The returned boolean indicates whether the
+    /// system call should be executed, and the returned state is passed along to
+    /// [`SyscallHandler::after_syscall`].
+    fn before_syscall(
+        &mut self,
+        context: &mut BeforeSyscallContext<'_>,
+        sysno: i32,
+    ) -> (bool, Self::State);
+
+    fn after_syscall(&mut self, context: &mut AfterSyscallContext<'_>, sysno: i32, state: Self::State);
+}
+
+/// Like [`SyscallHandler`], but the handler is shared between all application threads and is
+/// invoked through a shared reference rather than through a global lock. This allows the handler
+/// to use its own, more fine-grained synchronization.
+pub trait SyncSyscallHandler {
+    /// See [`SyscallHandler::State`].
+    type State;
+
+    fn filter_syscall(&self, context: &mut DrContext<'_>, sysno: i32) -> bool;
+    fn before_syscall(&self, context: &mut BeforeSyscallContext<'_>, sysno: i32) -> (bool, Self::State);
+    fn after_syscall(&self, context: &mut AfterSyscallContext<'_>, sysno: i32, state: Self::State);
+}
+
+impl<T: SyscallHandler> SyncSyscallHandler for Mutex<T> {
+    // The state is absent if the lock could not be acquired before the system call, in which
+    // case the handler is not invoked after the system call either.
+    type State = Option<T::State>;
+
+    fn filter_syscall(&self, context: &mut DrContext<'_>, sysno: i32) -> bool {
+        match self.lock() {
+            Ok(mut handler) => handler.filter_syscall(context, sysno),
+            _ => false,
+        }
+    }
+
+    fn before_syscall(&self, context: &mut BeforeSyscallContext<'_>, sysno: i32) -> (bool, Self::State) {
+        match self.lock() {
+            Ok(mut handler) => {
+                let (result, state) = handler.before_syscall(context, sysno);
+
+                (result, Some(state))
+            }
+            _ => (false, None),
+        }
+    }
+
+    fn after_syscall(&self, context: &mut AfterSyscallContext<'_>, sysno: i32, state: Self::State) {
+        let state = match state {
+            Some(state) => state,
+            _ => return,
+        };
+
+        if let Ok(mut handler) = self.lock() {
+            handler.after_syscall(context, sysno, state);
+        }
+    }
+}
+
+/// The handler along with the per-thread state that is in flight between the pre-syscall and
+/// post-syscall events. This is what the event closures point to.
+struct SyscallHandlerData<T: SyncSyscallHandler> {
+    handler: Arc<T>,
+    state: TlsKey<Option<T::State>>,
 }
 
-pub struct RegisteredSyscallHandler<T: SyscallHandler> {
-    _handler: Arc<Mutex<T>>,
-    closure: Closure,
+pub struct RegisteredSyncSyscallHandler<T: SyncSyscallHandler> {
+    _data: Box<SyscallHandlerData<T>>,
+    filter_syscall_closure: Closure,
+    before_syscall_closure: Closure,
+    after_syscall_closure: Closure,
+    _priority: Option<Priority>,
 }
 
-unsafe impl<T: SyscallHandler> Send for RegisteredSyscallHandler<T> {}
-unsafe impl<T: SyscallHandler> Sync for RegisteredSyscallHandler<T> {}
+pub type RegisteredSyscallHandler<T> = RegisteredSyncSyscallHandler<Mutex<T>>;
+
+unsafe impl<T: SyncSyscallHandler> Send for RegisteredSyncSyscallHandler<T> {}
+unsafe impl<T: SyncSyscallHandler> Sync for RegisteredSyncSyscallHandler<T> {}
 
-impl<T: SyscallHandler> Drop for RegisteredSyscallHandler<T> {
+impl<T: SyncSyscallHandler> Drop for RegisteredSyncSyscallHandler<T> {
     fn drop(&mut self) {
-        let func: extern "C" fn(*mut core::ffi::c_void, i32) -> i8 = unsafe {
-            core::mem::transmute(self.closure.code())
+        let filter_syscall_func: extern "C" fn(*mut core::ffi::c_void, i32) -> i8 = unsafe {
+            core::mem::transmute(self.filter_syscall_closure.code())
+        };
+
+        let before_syscall_func: extern "C" fn(*mut core::ffi::c_void, i32) -> i8 = unsafe {
+            core::mem::transmute(self.before_syscall_closure.code())
+        };
+
+        let after_syscall_func: extern "C" fn(*mut core::ffi::c_void, i32) = unsafe {
+            core::mem::transmute(self.after_syscall_closure.code())
         };
 
         unsafe {
             dr_unregister_filter_syscall_event(
-                Some(func),
+                Some(filter_syscall_func),
             );
-            drmgr_unregister_pre_syscall_event_user_data(
-                Some(before_syscall_event::<T>),
+            drmgr_unregister_pre_syscall_event(
+                Some(before_syscall_func),
             );
-            drmgr_unregister_post_syscall_event_user_data(
-                Some(after_syscall_event::<T>),
+            drmgr_unregister_post_syscall_event(
+                Some(after_syscall_func),
             );
         }
     }
 }
 
-extern "C" fn filter_syscall_event<T: SyscallHandler>(
+extern "C" fn filter_syscall_event<T: SyncSyscallHandler>(
     context: *mut core::ffi::c_void,
     sysnum: i32,
-    handler: &Mutex<T>,
+    data: &SyscallHandlerData<T>,
 ) -> i8 {
-    let mut context = Context::from_raw(context);
-    let mut result = false;
-
-    if let Ok(mut handler) = handler.lock() {
-        result = handler.filter_syscall(&mut context, sysnum);
-    }
+    let mut context = unsafe { DrContext::from_raw(context) };
 
-    result as i8
+    data.handler.filter_syscall(&mut context, sysnum) as i8
 }
 
-extern "C" fn before_syscall_event<T: SyscallHandler>(
+extern "C" fn before_syscall_event<T: SyncSyscallHandler>(
     context: *mut core::ffi::c_void,
     sysnum: i32,
-    user_data: *mut core::ffi::c_void,
+    data: &SyscallHandlerData<T>,
 ) -> i8 {
-    let mut context = BeforeSyscallContext::from_raw(context);
-    let handler = unsafe { &*(user_data as *mut Mutex<T>) };
-    let mut result = 0;
+    let mut context = unsafe { BeforeSyscallContext::from_raw(context) };
+    let (result, state) = data.handler.before_syscall(&mut context, sysnum);
 
-    if let Ok(mut handler) = handler.lock() {
-        result = handler.before_syscall(&mut context, sysnum) as i8;
+    // The state is private to this registration and only accessed from its own events, which do
+    // not overlap on the same thread.
+    unsafe {
+        *data.state.get_mut(context.context_mut()) = Some(state);
     }
 
-    result
+    result as i8
 }
 
-extern "C" fn after_syscall_event<T: SyscallHandler>(
+extern "C" fn after_syscall_event<T: SyncSyscallHandler>(
     context: *mut core::ffi::c_void,
     sysnum: i32,
-    user_data: *mut core::ffi::c_void,
+    data: &SyscallHandlerData<T>,
 ) {
-    let mut context = AfterSyscallContext::from_raw(context);
-    let handler = unsafe { &*(user_data as *mut Mutex<T>) };
+    let mut context = unsafe { AfterSyscallContext::from_raw(context) };
 
-    if let Ok(mut handler) = handler.lock() {
-        handler.after_syscall(&mut context, sysnum);
-    }
+    // The state may be missing if the pre-syscall event was not invoked for this system call,
+    // e.g. because the handler was registered in the middle of it.
+    let state = match unsafe { data.state.get_mut(context.context_mut()) }.take() {
+        Some(state) => state,
+        _ => return,
+    };
+
+    data.handler.after_syscall(&mut context, sysnum, state);
 }
 
 impl Manager {
@@ -99,39 +183,94 @@ impl Manager {
         Some(result as u32)
     }
 
+    /// Registers a handler for the system call events. Returns `None` if drmgr has run out of TLS
+    /// fields, as every registration uses one to pass the state of a system call along.
     pub fn register_syscall_handler<T: SyscallHandler>(
         &self,
         handler: &Arc<Mutex<T>>,
-    ) -> RegisteredSyscallHandler<T> {
-        let closure = Closure::new(
+        priority: Option<&Priority>,
+    ) -> Option<RegisteredSyscallHandler<T>> {
+        self.register_syscall_handler_inner(handler, priority)
+    }
+
+    /// Registers a handler that is shared between all application threads without being wrapped
+    /// in a [`Mutex`], such that the handler is responsible for its own synchronization. Returns
+    /// `None` if drmgr has run out of TLS fields.
+    pub fn register_sync_syscall_handler<T: SyncSyscallHandler + Send + Sync>(
+        &self,
+        handler: &Arc<T>,
+        priority: Option<&Priority>,
+    ) -> Option<RegisteredSyncSyscallHandler<T>> {
+        self.register_syscall_handler_inner(handler, priority)
+    }
+
+    fn register_syscall_handler_inner<T: SyncSyscallHandler>(
+        &self,
+        handler: &Arc<T>,
+        priority: Option<&Priority>,
+    ) -> Option<RegisteredSyncSyscallHandler<T>> {
+        let data = Box::new(SyscallHandlerData {
+            handler: Arc::clone(handler),
+            state: TlsKey::new(|_| None)?,
+        });
+
+        let filter_syscall_closure = Closure::new(
             2,
             unsafe {
                 core::mem::transmute(filter_syscall_event::<T> as extern "C" fn(_, _, _) -> _)
             },
-            Arc::as_ptr(&handler) as *mut core::ffi::c_void,
+            &*data as *const SyscallHandlerData<T> as *mut core::ffi::c_void,
+        );
+
+        let filter_syscall_func: extern "C" fn(*mut core::ffi::c_void, i32) -> i8 = unsafe {
+            core::mem::transmute(filter_syscall_closure.code())
+        };
+
+        let before_syscall_closure = Closure::new(
+            2,
+            unsafe {
+                core::mem::transmute(before_syscall_event::<T> as extern "C" fn(_, _, _) -> _)
+            },
+            &*data as *const SyscallHandlerData<T> as *mut core::ffi::c_void,
+        );
+
+        let before_syscall_func: extern "C" fn(*mut core::ffi::c_void, i32) -> i8 = unsafe {
+            core::mem::transmute(before_syscall_closure.code())
+        };
+
+        let after_syscall_closure = Closure::new(
+            2,
+            unsafe {
+                core::mem::transmute(after_syscall_event::<T> as extern "C" fn(_, _, _))
+            },
+            &*data as *const SyscallHandlerData<T> as *mut core::ffi::c_void,
         );
 
-        let func: extern "C" fn(*mut core::ffi::c_void, i32) -> i8 = unsafe {
-            core::mem::transmute(closure.code())
+        let after_syscall_func: extern "C" fn(*mut core::ffi::c_void, i32) = unsafe {
+            core::mem::transmute(after_syscall_closure.code())
         };
 
+        let priority = priority.cloned();
+        let mut raw_priority = priority.as_ref().map(Priority::raw);
+
         unsafe {
-            dr_register_filter_syscall_event(Some(func));
-            drmgr_register_pre_syscall_event_user_data(
-                Some(before_syscall_event::<T>),
-                core::ptr::null_mut(),
-                Arc::as_ptr(&handler) as *mut core::ffi::c_void,
+            dr_register_filter_syscall_event(Some(filter_syscall_func));
+            drmgr_register_pre_syscall_event_ex(
+                Some(before_syscall_func),
+                raw_priority_ptr(&mut raw_priority),
             );
-            drmgr_register_post_syscall_event_user_data(
-                Some(after_syscall_event::<T>),
-                core::ptr::null_mut(),
-                Arc::as_ptr(&handler) as *mut core::ffi::c_void,
+            drmgr_register_post_syscall_event_ex(
+                Some(after_syscall_func),
+                raw_priority_ptr(&mut raw_priority),
             );
         }
 
-        RegisteredSyscallHandler {
-            _handler: Arc::clone(&handler),
-            closure,
-        }
+        Some(RegisteredSyncSyscallHandler {
+            _data: data,
+            filter_syscall_closure,
+            before_syscall_closure,
+            after_syscall_closure,
+            _priority: priority,
+        })
     }
 }
@@ -1,27 +1,48 @@
-use crate::{Context, Manager};
+use crate::{DrContext, Manager, Priority};
+use crate::closure::Closure;
+use crate::manager::priority::raw_priority_ptr;
 use drstd::sync::{Arc, Mutex};
 use dynamorio_sys::*;
 
+/// Handler for thread creation and exit. Per-thread state can be kept in a [`TlsKey`], which is
+/// initialized before [`ThreadHandler::create_thread`] and dropped after
+/// [`ThreadHandler::exit_thread`] is invoked for the same thread.
+///
+/// [`TlsKey`]: crate::TlsKey
 pub trait ThreadHandler {
-    fn create_thread(&mut self, context: &mut Context);
-    fn exit_thread(&mut self, context: &mut Context);
+    fn create_thread(&mut self, context: &mut DrContext<'_>);
+    fn exit_thread(&mut self, context: &mut DrContext<'_>);
 }
 
 pub struct RegisteredThreadHandler<T: ThreadHandler> {
     _handler: Arc<Mutex<T>>,
+    thread_init_closure: Closure,
+    thread_exit_closure: Closure,
+    _priority: Option<Priority>,
 }
 
+unsafe impl<T: ThreadHandler> Send for RegisteredThreadHandler<T> {}
+unsafe impl<T: ThreadHandler> Sync for RegisteredThreadHandler<T> {}
+
 impl<T: ThreadHandler> Drop for RegisteredThreadHandler<T> {
     fn drop(&mut self) {
+        let thread_init_func: extern "C" fn(*mut core::ffi::c_void) = unsafe {
+            core::mem::transmute(self.thread_init_closure.code())
+        };
+
+        let thread_exit_func: extern "C" fn(*mut core::ffi::c_void) = unsafe {
+            core::mem::transmute(self.thread_exit_closure.code())
+        };
+
         unsafe {
-            drmgr_unregister_thread_init_event_user_data(
-                Some(thread_init_event::<T>),
+            drmgr_unregister_thread_init_event(
+                Some(thread_init_func),
             );
         }
 
         unsafe {
-            drmgr_unregister_thread_exit_event_user_data(
-                Some(thread_exit_event::<T>),
+            drmgr_unregister_thread_exit_event(
+                Some(thread_exit_func),
             );
         }
     }
@@ -29,10 +50,9 @@ impl<T: ThreadHandler> Drop for RegisteredThreadHandler<T> {
 
 extern "C" fn thread_init_event<T: ThreadHandler>(
     context: *mut core::ffi::c_void,
-    user_data: *mut core::ffi::c_void,
+    handler: &Mutex<T>,
 ) {
-    let mut context = Context::from_raw(context);
-    let handler = unsafe { &*(user_data as *mut Mutex<T>) };
+    let mut context = unsafe { DrContext::from_raw(context) };
 
     if let Ok(mut handler) = handler.lock() {
         handler.create_thread(&mut context);
@@ -41,10 +61,9 @@ extern "C" fn thread_init_event<T: ThreadHandler>(
 
 extern "C" fn thread_exit_event<T: ThreadHandler>(
     context: *mut core::ffi::c_void,
-    user_data: *mut core::ffi::c_void,
+    handler: &Mutex<T>,
 ) {
-    let mut context = Context::from_raw(context);
-    let handler = unsafe { &*(user_data as *mut Mutex<T>) };
+    let mut context = unsafe { DrContext::from_raw(context) };
 
     if let Ok(mut handler) = handler.lock() {
         handler.exit_thread(&mut context);
@@ -55,22 +74,51 @@ impl Manager {
     pub fn register_thread_handler<T: ThreadHandler>(
         &self,
         handler: &Arc<Mutex<T>>,
+        priority: Option<&Priority>,
     ) -> RegisteredThreadHandler<T> {
+        let priority = priority.cloned();
+        let mut raw_priority = priority.as_ref().map(Priority::raw);
+
+        let thread_init_closure = Closure::new(
+            1,
+            unsafe {
+                core::mem::transmute(thread_init_event::<T> as extern "C" fn(_, _))
+            },
+            Arc::as_ptr(handler) as *mut core::ffi::c_void,
+        );
+
+        let thread_init_func: extern "C" fn(*mut core::ffi::c_void) = unsafe {
+            core::mem::transmute(thread_init_closure.code())
+        };
+
+        let thread_exit_closure = Closure::new(
+            1,
+            unsafe {
+                core::mem::transmute(thread_exit_event::<T> as extern "C" fn(_, _))
+            },
+            Arc::as_ptr(handler) as *mut core::ffi::c_void,
+        );
+
+        let thread_exit_func: extern "C" fn(*mut core::ffi::c_void) = unsafe {
+            core::mem::transmute(thread_exit_closure.code())
+        };
+
         unsafe {
-            drmgr_register_thread_init_event_user_data(
-                Some(thread_init_event::<T>),
-                core::ptr::null_mut(),
-                Arc::as_ptr(&handler) as *mut core::ffi::c_void,
+            drmgr_register_thread_init_event_ex(
+                Some(thread_init_func),
+                raw_priority_ptr(&mut raw_priority),
             );
-            drmgr_register_thread_exit_event_user_data(
-                Some(thread_exit_event::<T>),
-                core::ptr::null_mut(),
-                Arc::as_ptr(&handler) as *mut core::ffi::c_void,
+            drmgr_register_thread_exit_event_ex(
+                Some(thread_exit_func),
+                raw_priority_ptr(&mut raw_priority),
             );
         }
 
         RegisteredThreadHandler {
-            _handler: Arc::clone(&handler),
+            _handler: Arc::clone(handler),
+            thread_init_closure,
+            thread_exit_closure,
+            _priority: priority,
         }
     }
 }
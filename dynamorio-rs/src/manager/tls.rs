@@ -0,0 +1,198 @@
+use crate::{DrContext, Manager, Priority};
+use crate::closure::Closure;
+use alloc::boxed::Box;
+use dynamorio_sys::*;
+
+type ThreadEventFunc = extern "C" fn(*mut core::ffi::c_void);
+
+struct TlsField<T> {
+    index: i32,
+    init: Box<dyn Fn(&DrContext<'_>) -> T + Send + Sync>,
+}
+
+impl<T> TlsField<T> {
+    /// Returns a pointer to the value of the current thread, initializing it first if the thread
+    /// does not have one yet, e.g. because it was already running when the field was registered.
+    fn get_or_init(&self, context: &DrContext<'_>) -> *mut T {
+        let value = unsafe {
+            drmgr_get_tls_field(context.raw(), self.index)
+        } as *mut T;
+
+        if !value.is_null() {
+            return value;
+        }
+
+        let value = Box::into_raw(Box::new((self.init)(context)));
+
+        unsafe {
+            drmgr_set_tls_field(context.raw(), self.index, value as *mut core::ffi::c_void);
+        }
+
+        value
+    }
+}
+
+extern "C" fn tls_thread_init_event<T>(
+    context: *mut core::ffi::c_void,
+    field: &TlsField<T>,
+) {
+    let context = unsafe { DrContext::from_raw(context) };
+
+    field.get_or_init(&context);
+}
+
+extern "C" fn tls_thread_exit_event<T>(
+    context: *mut core::ffi::c_void,
+    field: &TlsField<T>,
+) {
+    let value = unsafe {
+        drmgr_get_tls_field(context, field.index)
+    } as *mut T;
+
+    if value.is_null() {
+        return;
+    }
+
+    unsafe {
+        drmgr_set_tls_field(context, field.index, core::ptr::null_mut());
+    }
+
+    drop(unsafe { Box::from_raw(value) });
+}
+
+/// A drmgr TLS field that holds a value of type `T` for every thread. The value is initialized
+/// before any other thread init event is invoked, and dropped after every other thread exit event
+/// has been invoked. As such, the value is available from [`ThreadHandler::create_thread`] up to
+/// and including [`ThreadHandler::exit_thread`]. Threads that were already running when the key
+/// was created are initialized on first access.
+///
+/// Values that belong to threads that are still alive when the key is dropped are leaked.
+///
+/// [`ThreadHandler::create_thread`]: crate::ThreadHandler::create_thread
+/// [`ThreadHandler::exit_thread`]: crate::ThreadHandler::exit_thread
+pub struct TlsKey<T> {
+    field: Box<TlsField<T>>,
+    thread_init_closure: Closure,
+    thread_exit_closure: Closure,
+    thread_init_priority: Priority,
+    thread_exit_priority: Priority,
+}
+
+unsafe impl<T: Send> Send for TlsKey<T> {}
+unsafe impl<T: Send> Sync for TlsKey<T> {}
+
+impl<T> TlsKey<T> {
+    pub(crate) fn new<F>(init: F) -> Option<Self>
+    where
+        F: Fn(&DrContext<'_>) -> T + Send + Sync + 'static,
+    {
+        let index = unsafe {
+            drmgr_register_tls_field()
+        };
+
+        if index < 0 {
+            return None;
+        }
+
+        let field = Box::new(TlsField {
+            index,
+            init: Box::new(init),
+        });
+
+        let thread_init_closure = Closure::new(
+            1,
+            unsafe {
+                core::mem::transmute(tls_thread_init_event::<T> as extern "C" fn(_, _))
+            },
+            &*field as *const TlsField<T> as *mut core::ffi::c_void,
+        );
+
+        let thread_exit_closure = Closure::new(
+            1,
+            unsafe {
+                core::mem::transmute(tls_thread_exit_event::<T> as extern "C" fn(_, _))
+            },
+            &*field as *const TlsField<T> as *mut core::ffi::c_void,
+        );
+
+        let key = Self {
+            field,
+            thread_init_closure,
+            thread_exit_closure,
+            thread_init_priority: Priority::new(c"dynamorio-rs tls init").priority(i32::MIN),
+            thread_exit_priority: Priority::new(c"dynamorio-rs tls exit").priority(i32::MAX),
+        };
+
+        let (thread_init_func, thread_exit_func) = key.callbacks();
+        let mut thread_init_priority = key.thread_init_priority.raw();
+        let mut thread_exit_priority = key.thread_exit_priority.raw();
+
+        unsafe {
+            drmgr_register_thread_init_event_ex(
+                Some(thread_init_func),
+                &mut thread_init_priority,
+            );
+            drmgr_register_thread_exit_event_ex(
+                Some(thread_exit_func),
+                &mut thread_exit_priority,
+            );
+        }
+
+        Some(key)
+    }
+
+    fn callbacks(&self) -> (ThreadEventFunc, ThreadEventFunc) {
+        unsafe {
+            (
+                core::mem::transmute::<*mut core::ffi::c_void, ThreadEventFunc>(self.thread_init_closure.code()),
+                core::mem::transmute::<*mut core::ffi::c_void, ThreadEventFunc>(self.thread_exit_closure.code()),
+            )
+        }
+    }
+
+    /// Returns a reference to the value of the thread that `context` belongs to.
+    pub fn get<'a>(&'a self, context: &'a DrContext<'_>) -> &'a T {
+        unsafe {
+            &*self.field.get_or_init(context)
+        }
+    }
+
+    /// Returns a mutable reference to the value of the thread that `context` belongs to.
+    ///
+    /// # Safety
+    ///
+    /// As [`DrContext::current`] can hand out more than one `DrContext` for the same thread, the
+    /// borrow of `context` does not rule out aliasing. The caller must ensure that no other
+    /// reference to the value of this thread, as returned by [`TlsKey::get`] or
+    /// [`TlsKey::get_mut`], is live for as long as the returned reference is.
+    pub unsafe fn get_mut<'a>(&'a self, context: &'a mut DrContext<'_>) -> &'a mut T {
+        &mut *self.field.get_or_init(context)
+    }
+}
+
+impl<T> Drop for TlsKey<T> {
+    fn drop(&mut self) {
+        let (thread_init_func, thread_exit_func) = self.callbacks();
+
+        unsafe {
+            drmgr_unregister_thread_init_event(
+                Some(thread_init_func),
+            );
+            drmgr_unregister_thread_exit_event(
+                Some(thread_exit_func),
+            );
+            drmgr_unregister_tls_field(self.field.index);
+        }
+    }
+}
+
+impl Manager {
+    /// Registers a new drmgr TLS field, where the value of every thread is initialized by calling
+    /// `init` when the thread is created. Returns `None` if no more TLS fields are available.
+    pub fn tls<T, F>(&self, init: F) -> Option<TlsKey<T>>
+    where
+        F: Fn(&DrContext<'_>) -> T + Send + Sync + 'static,
+    {
+        TlsKey::new(init)
+    }
+}
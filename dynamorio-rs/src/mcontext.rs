@@ -1,6 +1,19 @@
 use dynamorio_sys::*;
 
+/// The machine context of a thread. This is a transparent wrapper around `dr_mcontext_t`, such that
+/// references to the machine contexts passed to events can be handed out directly.
 #[derive(Clone, Copy)]
+#[repr(transparent)]
 pub struct MachineContext {
-    pub(crate) _mcontext: dr_mcontext_t,
+    pub(crate) mcontext: dr_mcontext_t,
+}
+
+impl MachineContext {
+    pub fn raw(&self) -> &dr_mcontext_t {
+        &self.mcontext
+    }
+
+    pub fn raw_mut(&mut self) -> &mut dr_mcontext_t {
+        &mut self.mcontext
+    }
 }
@@ -0,0 +1,60 @@
+use core::ffi::CStr;
+use core::fmt;
+use dynamorio_sys::*;
+
+/// An instruction opcode as used by DynamoRIO, i.e. one of the `OP_*` constants. The opcodes that
+/// are commonly instrumented are available as associated constants for the target architecture,
+/// such that they can be matched on, while any other opcode can be converted from its raw value.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Opcode(pub(crate) u32);
+
+impl Opcode {
+    pub const INVALID: Self = Self(OP_INVALID);
+    pub const UNDECODED: Self = Self(OP_UNDECODED);
+
+    pub const fn from_raw(opcode: u32) -> Self {
+        Self(opcode)
+    }
+
+    pub fn raw(&self) -> u32 {
+        self.0
+    }
+
+    /// Returns the name of the opcode as used by DynamoRIO's disassembler, e.g. `"mov"`, or `None`
+    /// if the opcode is out of range.
+    pub fn name(&self) -> Option<&'static str> {
+        if self.0 >= OP_AFTER_LAST {
+            return None;
+        }
+
+        let name = unsafe {
+            decode_opcode_name(self.0 as i32)
+        };
+
+        if name.is_null() {
+            return None;
+        }
+
+        unsafe {
+            CStr::from_ptr(name).to_str().ok()
+        }
+    }
+}
+
+impl From<u32> for Opcode {
+    fn from(opcode: u32) -> Self {
+        Self::from_raw(opcode)
+    }
+}
+
+impl From<Opcode> for u32 {
+    fn from(opcode: Opcode) -> Self {
+        opcode.raw()
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name().unwrap_or("<invalid>"))
+    }
+}
@@ -1,4 +1,6 @@
-use crate::Instruction;
+use crate::InstrRef;
+#[cfg(target_arch = "x86_64")]
+use crate::Register;
 use dynamorio_sys::*;
 
 #[derive(Clone, Copy, Debug)]
@@ -15,9 +17,10 @@ impl PartialEq for Operand {
 }
 
 impl Operand {
-    pub fn new_register(register: reg_id_t) -> Self {
+    #[cfg(target_arch = "x86_64")]
+    pub fn new_register(register: Register) -> Self {
         let raw = unsafe {
-            opnd_create_reg(register)
+            opnd_create_reg(register.raw())
         };
 
         Self {
@@ -35,9 +38,10 @@ impl Operand {
         }
     }
 
-    pub fn new_memptr(base: reg_id_t, displacement: i32) -> Self {
+    #[cfg(target_arch = "x86_64")]
+    pub fn new_memptr(base: Register, displacement: i32) -> Self {
         let raw = unsafe {
-            opnd_create_base_disp(base, DR_REG_NULL as _, 0, displacement, OPSZ_8 as _)
+            opnd_create_base_disp(base.raw(), DR_REG_NULL as _, 0, displacement, OPSZ_8 as _)
         };
 
         Self {
@@ -57,24 +61,44 @@ impl Operand {
         }
     }
 
-    pub fn register(&self) -> Option<reg_id_t> {
+    /// Returns the register of a register operand. Returns `None` if this is not a register
+    /// operand, or if the register is not covered by [`Register`], in which case
+    /// [`Operand::register_id`] still returns it.
+    #[cfg(target_arch = "x86_64")]
+    pub fn register(&self) -> Option<Register> {
+        Register::from_raw(self.register_id()?)
+    }
+
+    /// Returns the raw register ID of a register operand, or `None` if this is not a register
+    /// operand.
+    pub fn register_id(&self) -> Option<reg_id_t> {
         let register = unsafe {
             opnd_get_reg(self.raw)
         };
 
-        if register == DR_REG_NULL as u16 {
+        if register == DR_REG_NULL as reg_id_t {
             return None;
         }
 
         Some(register)
     }
 
-    pub fn segment(&self) -> Option<reg_id_t> {
+    /// Returns the segment register of the operand. Returns `None` if there is none, or if the
+    /// register is not covered by [`Register`], in which case [`Operand::segment_id`] still
+    /// returns it.
+    #[cfg(target_arch = "x86_64")]
+    pub fn segment(&self) -> Option<Register> {
+        Register::from_raw(self.segment_id()?)
+    }
+
+    /// Returns the raw register ID of the segment register of the operand, or `None` if there is
+    /// none.
+    pub fn segment_id(&self) -> Option<reg_id_t> {
         let register = unsafe {
             opnd_get_segment(self.raw)
         };
 
-        if register == DR_REG_NULL as u16 {
+        if register == DR_REG_NULL as reg_id_t {
             return None;
         }
 
@@ -90,7 +114,7 @@ impl Operand {
 
 #[derive(Debug)]
 pub struct SourceOperandIter<'a> {
-    pub(crate) instruction: &'a Instruction,
+    pub(crate) instruction: InstrRef<'a>,
     pub(crate) index: usize,
     pub(crate) count: usize,
 }
@@ -117,7 +141,7 @@ impl<'a> Iterator for SourceOperandIter<'a> {
 
 #[derive(Debug)]
 pub struct TargetOperandIter<'a> {
-    pub(crate) instruction: &'a Instruction,
+    pub(crate) instruction: InstrRef<'a>,
     pub(crate) index: usize,
     pub(crate) count: usize,
 }
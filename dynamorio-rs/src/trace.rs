@@ -0,0 +1,176 @@
+use crate::{BlockTag, DrContext, InstrListRef};
+use crate::closure::Closure;
+use drstd::sync::{Arc, Mutex};
+use dynamorio_sys::*;
+
+type TraceFunc = extern "C" fn(*mut core::ffi::c_void, *mut core::ffi::c_void, *mut instrlist_t, i8) -> dr_emit_flags_t;
+type EndTraceFunc = extern "C" fn(*mut core::ffi::c_void, *mut core::ffi::c_void, *mut core::ffi::c_void) -> dr_custom_trace_action_t;
+
+/// The decision whether to end a trace that is being built.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TraceAction {
+    /// Let DynamoRIO decide using its default heuristics.
+    Default,
+    /// End the trace before the next basic block.
+    End,
+    /// Add the next basic block to the trace.
+    Continue,
+}
+
+/// Handler for traces, i.e. the superblocks that DynamoRIO builds out of frequently executed
+/// sequences of basic blocks.
+pub trait TraceHandler {
+    /// Invoked when a trace is built, before it is emitted into the code cache. The trace consists
+    /// of the already instrumented basic blocks, which may be instrumented further.
+    fn instrument_trace(
+        &mut self,
+        context: &mut DrContext<'_>,
+        tag: BlockTag,
+        trace: &mut InstrListRef<'_>,
+        translating: bool,
+    ) -> dr_emit_flags_t;
+
+    /// Invoked when the basic block with tag `next_tag` is about to be added to the trace that
+    /// starts at `trace_tag`, such that the handler can decide where traces end.
+    fn end_trace(
+        &mut self,
+        _context: &mut DrContext<'_>,
+        _trace_tag: BlockTag,
+        _next_tag: BlockTag,
+    ) -> TraceAction {
+        TraceAction::Default
+    }
+}
+
+extern "C" fn trace_event<T: TraceHandler>(
+    context: *mut core::ffi::c_void,
+    tag: *mut core::ffi::c_void,
+    trace: *mut instrlist_t,
+    translating: i8,
+    handler: &Mutex<T>,
+) -> dr_emit_flags_t {
+    let mut trace = unsafe { InstrListRef::from_raw(context, trace) };
+    let translating = translating != 0;
+    let tag = BlockTag::from_raw(tag);
+    let mut context = unsafe { DrContext::from_raw(context) };
+    let mut flags = dr_emit_flags_t::DR_EMIT_DEFAULT;
+
+    if let Ok(mut handler) = handler.lock() {
+        flags = handler.instrument_trace(&mut context, tag, &mut trace, translating);
+    }
+
+    flags
+}
+
+extern "C" fn end_trace_event<T: TraceHandler>(
+    context: *mut core::ffi::c_void,
+    trace_tag: *mut core::ffi::c_void,
+    next_tag: *mut core::ffi::c_void,
+    handler: &Mutex<T>,
+) -> dr_custom_trace_action_t {
+    let mut context = unsafe { DrContext::from_raw(context) };
+    let trace_tag = BlockTag::from_raw(trace_tag);
+    let next_tag = BlockTag::from_raw(next_tag);
+    let mut action = TraceAction::Default;
+
+    if let Ok(mut handler) = handler.lock() {
+        action = handler.end_trace(&mut context, trace_tag, next_tag);
+    }
+
+    match action {
+        TraceAction::Default => dr_custom_trace_action_t::CUSTOM_TRACE_DR_DECIDES,
+        TraceAction::End => dr_custom_trace_action_t::CUSTOM_TRACE_END_NOW,
+        TraceAction::Continue => dr_custom_trace_action_t::CUSTOM_TRACE_CONTINUE,
+    }
+}
+
+pub struct RegisteredTraceHandler<T: TraceHandler> {
+    _handler: Arc<Mutex<T>>,
+    trace_closure: Closure,
+    end_trace_closure: Closure,
+}
+
+unsafe impl<T: TraceHandler> Send for RegisteredTraceHandler<T> {}
+unsafe impl<T: TraceHandler> Sync for RegisteredTraceHandler<T> {}
+
+impl<T: TraceHandler> RegisteredTraceHandler<T> {
+    fn callbacks(&self) -> (TraceFunc, EndTraceFunc) {
+        unsafe {
+            (
+                core::mem::transmute::<*mut core::ffi::c_void, TraceFunc>(self.trace_closure.code()),
+                core::mem::transmute::<*mut core::ffi::c_void, EndTraceFunc>(self.end_trace_closure.code()),
+            )
+        }
+    }
+}
+
+impl<T: TraceHandler> Drop for RegisteredTraceHandler<T> {
+    fn drop(&mut self) {
+        let (trace, end_trace) = self.callbacks();
+
+        unsafe {
+            dr_unregister_trace_event(Some(trace));
+            dr_unregister_end_trace_event(Some(end_trace));
+        }
+    }
+}
+
+/// Registers a handler for the trace events. DynamoRIO invokes the handler whenever it builds a
+/// trace, as well as for every basic block that it considers adding to a trace.
+pub fn register_trace_handler<T: TraceHandler>(handler: &Arc<Mutex<T>>) -> RegisteredTraceHandler<T> {
+    let trace_closure = Closure::new(
+        4,
+        unsafe {
+            core::mem::transmute(trace_event::<T> as extern "C" fn(_, _, _, _, _) -> _)
+        },
+        Arc::as_ptr(handler) as *mut core::ffi::c_void,
+    );
+
+    let end_trace_closure = Closure::new(
+        3,
+        unsafe {
+            core::mem::transmute(end_trace_event::<T> as extern "C" fn(_, _, _, _) -> _)
+        },
+        Arc::as_ptr(handler) as *mut core::ffi::c_void,
+    );
+
+    let registered = RegisteredTraceHandler {
+        _handler: Arc::clone(handler),
+        trace_closure,
+        end_trace_closure,
+    };
+
+    let (trace, end_trace) = registered.callbacks();
+
+    unsafe {
+        dr_register_trace_event(Some(trace));
+        dr_register_end_trace_event(Some(end_trace));
+    }
+
+    registered
+}
+
+impl DrContext<'_> {
+    /// Marks the basic block with the given tag as a trace head, such that DynamoRIO starts
+    /// building a trace from it once it has been executed often enough. Returns whether the tag
+    /// was marked successfully.
+    pub fn mark_trace_head(&mut self, tag: BlockTag) -> bool {
+        unsafe {
+            dr_mark_trace_head(self.context, tag.raw()) != 0
+        }
+    }
+
+    /// Returns whether the basic block with the given tag is a trace head.
+    pub fn is_trace_head(&self, tag: BlockTag) -> bool {
+        unsafe {
+            dr_trace_head_at(self.context, tag.raw()) != 0
+        }
+    }
+
+    /// Returns whether a trace exists that starts at the given tag.
+    pub fn trace_exists(&self, tag: BlockTag) -> bool {
+        unsafe {
+            dr_trace_exists_at(self.context, tag.raw()) != 0
+        }
+    }
+}
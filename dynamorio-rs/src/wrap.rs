@@ -31,7 +31,7 @@ macro_rules! wrap {
                 $($arg_name : $arg_ty ,)*
                 //handler: &Mutex<T>,
             ) $(-> $ret)? {
-                let context = Context::current();
+                let context = DrContext::current();
                 let handler: &Mutex<T> = core::mem::transmute(
                     context.read_saved_register(dr_spill_slot_t::SPILL_SLOT_2)
                 );
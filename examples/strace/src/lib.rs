@@ -4,7 +4,6 @@ extern crate alloc;
 
 use alloc::format;
 use alloc::string::String;
-use alloc::vec;
 use alloc::vec::Vec;
 use drstd::*;
 use drstd::sync::{Arc, Mutex, Once};
@@ -13,37 +12,32 @@ use syscalls::Sysno;
 
 struct Client {
     registered_syscall_handler: Option<RegisteredSyscallHandler<Self>>,
-    sysno: Option<Sysno>,
-    arguments: Vec<u64>,
 }
 
 static CLIENT: Once<Arc<Mutex<Client>>> = Once::new();
 
 impl SyscallHandler for Client {
-    fn filter_syscall(&mut self, _context: &mut Context, _sysnum: i32) -> bool {
+    type State = (Sysno, Vec<u64>);
+
+    fn filter_syscall(&mut self, _context: &mut DrContext, _sysnum: i32) -> bool {
         true
     }
 
-    fn before_syscall(&mut self, context: &mut BeforeSyscallContext, sysnum: i32) -> bool {
+    fn before_syscall(&mut self, context: &mut BeforeSyscallContext, sysnum: i32) -> (bool, Self::State) {
         let sysno = Sysno::from(sysnum);
-
-        self.sysno = Some(sysno);
-        self.arguments = sysno.arguments()
+        let arguments = sysno.arguments()
             .iter()
             .enumerate()
             .map(|(i, _argument)| unsafe { context.param(i) })
             .collect::<Vec<u64>>();
 
-        true
+        (true, (sysno, arguments))
     }
 
-    fn after_syscall(&mut self, context: &mut AfterSyscallContext, _sysnum: i32) {
-        let sysno = match self.sysno.take() {
-            Some(sysno) => sysno,
-            _ => return,
-        };
+    fn after_syscall(&mut self, context: &mut AfterSyscallContext, _sysnum: i32, state: Self::State) {
+        let (sysno, arguments) = state;
 
-        let arguments = self.arguments
+        let arguments = arguments
             .iter()
             .map(|argument| format!("0x{argument:x}"))
             .collect::<Vec<String>>()
@@ -61,14 +55,12 @@ fn client_main(_id: ClientId, _args: &[&str]) {
     CLIENT.call_once(|| {
         let client = Arc::new(Mutex::new(Client {
             registered_syscall_handler: None,
-            sysno: None,
-            arguments: vec![],
         }));
 
-        let registered_syscall_handler = manager.register_syscall_handler(&client);
+        let registered_syscall_handler = manager.register_syscall_handler(&client, None);
 
         if let Ok(mut client) = client.lock() {
-            client.registered_syscall_handler = Some(registered_syscall_handler);
+            client.registered_syscall_handler = registered_syscall_handler;
         }
 
         client
@@ -0,0 +1,22 @@
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=DRIO_BUILD_DIR");
+
+    // Like dynamorio-sys, use an out-of-tree build of DynamoRIO if one is specified, and a
+    // system-wide installation otherwise.
+    if let Some(build_dir) = env::var_os("DRIO_BUILD_DIR") {
+        let lib_dir = if env::var("CARGO_CFG_TARGET_POINTER_WIDTH").as_deref() == Ok("64") {
+            "lib64"
+        } else {
+            "lib32"
+        };
+
+        let path = PathBuf::from(build_dir).join(lib_dir);
+
+        println!("cargo:rustc-link-search=native={}", path.display());
+    }
+
+    println!("cargo:rustc-link-lib=drconfiglib");
+}
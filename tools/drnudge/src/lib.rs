@@ -0,0 +1,83 @@
+//! Host-side library to nudge clients running inside a DynamoRIO process, e.g. to make a client
+//! dump its statistics. This links against drconfiglib from a system-wide installation of
+//! DynamoRIO, or from the out-of-tree build in `DRIO_BUILD_DIR` if set at build time.
+
+use std::fmt;
+use std::time::Duration;
+
+type DrConfigStatus = u32;
+
+// The values of `dr_config_status_t` as declared in `dr_config.h` of DynamoRIO 11.90, the version
+// that `dynamorio-sys` is built against. The enum is not part of the bindings, as drconfiglib is a
+// host-side library, so these must be kept in sync when moving to another DynamoRIO release.
+const DR_SUCCESS: DrConfigStatus = 0;
+const DR_FAILURE: DrConfigStatus = 6;
+const DR_NUDGE_PID_NOT_INJECTED: DrConfigStatus = 7;
+const DR_NUDGE_TIMEOUT: DrConfigStatus = 8;
+const DR_NUDGE_PID_NOT_FOUND: DrConfigStatus = 11;
+
+extern "C" {
+    fn dr_nudge_pid(
+        process_id: u32,
+        client_id: u32,
+        argument: u64,
+        timeout_ms: u32,
+    ) -> DrConfigStatus;
+}
+
+/// The error returned when a nudge could not be delivered.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NudgeError {
+    /// There is no process with the given process ID.
+    ProcessNotFound,
+    /// The process is not running under DynamoRIO.
+    NotInjected,
+    /// The nudge was delivered, but did not complete within the timeout.
+    Timeout,
+    /// The nudge failed for another reason.
+    Failure,
+    /// drconfiglib returned a status that is not known to this crate.
+    Unknown(u32),
+}
+
+impl fmt::Display for NudgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ProcessNotFound => write!(f, "no such process"),
+            Self::NotInjected => write!(f, "process is not running under DynamoRIO"),
+            Self::Timeout => write!(f, "timed out waiting for the nudge to complete"),
+            Self::Failure => write!(f, "failed to nudge the process"),
+            Self::Unknown(status) => write!(f, "failed to nudge the process (status {status})"),
+        }
+    }
+}
+
+impl std::error::Error for NudgeError {}
+
+/// Nudges the client with the given ID in the process with the given process ID, which invokes
+/// the nudge handler of the client with the given argument. If `timeout` is `None`, this waits
+/// indefinitely for the nudge to complete.
+pub fn nudge(
+    process_id: u32,
+    client_id: u32,
+    argument: u64,
+    timeout: Option<Duration>,
+) -> Result<(), NudgeError> {
+    let timeout_ms = match timeout {
+        Some(timeout) => timeout.as_millis().min(u32::MAX as u128 - 1) as u32,
+        None => u32::MAX,
+    };
+
+    let status = unsafe {
+        dr_nudge_pid(process_id, client_id, argument, timeout_ms)
+    };
+
+    match status {
+        DR_SUCCESS => Ok(()),
+        DR_NUDGE_PID_NOT_FOUND => Err(NudgeError::ProcessNotFound),
+        DR_NUDGE_PID_NOT_INJECTED => Err(NudgeError::NotInjected),
+        DR_NUDGE_TIMEOUT => Err(NudgeError::Timeout),
+        DR_FAILURE => Err(NudgeError::Failure),
+        status => Err(NudgeError::Unknown(status)),
+    }
+}
@@ -0,0 +1,49 @@
+use std::process::ExitCode;
+use std::time::Duration;
+
+const USAGE: &str = "usage: drnudge <pid> <client-id> <argument> [timeout-ms]";
+
+/// Parses a decimal or `0x`-prefixed hexadecimal number.
+fn parse_number(value: &str) -> Option<u64> {
+    match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.len() < 3 || args.len() > 4 {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    }
+
+    let numbers: Option<Vec<u64>> = args.iter().map(|arg| parse_number(arg)).collect();
+
+    let numbers = match numbers {
+        Some(numbers) => numbers,
+        None => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let (process_id, client_id) = match (u32::try_from(numbers[0]), u32::try_from(numbers[1])) {
+        (Ok(process_id), Ok(client_id)) => (process_id, client_id),
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let timeout = numbers.get(3).map(|&timeout_ms| Duration::from_millis(timeout_ms));
+
+    match drnudge::nudge(process_id, client_id, numbers[2], timeout) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("drnudge: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}